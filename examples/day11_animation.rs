@@ -0,0 +1,54 @@
+/**
+ * Evolution of a day 11 seat layout, in the terminal or as an animated GIF
+ *
+ * cargo run --example day11_animation -- [layout] [--visible] [--delay <ms>] [--max <generations>] [--gif <file>]
+ *
+ * Layouts still changing are cut after day11::MAX_GENERATIONS generations,
+ * like in the solver, unless --max says otherwise.
+ */
+extern crate aoc_2020;
+
+use std::env;
+use std::fs;
+use std::io;
+use std::process;
+use std::time::Duration;
+use aoc_2020::day11::{self, Animation};
+
+fn main() {
+    let mut path = String::from("input/2020/day11.txt");
+    let mut rules = day11::ADJACENT;
+    let mut delay: u64 = 200;
+    let mut max = day11::MAX_GENERATIONS;
+    let mut gif: Option<String> = None;
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--visible" => rules = day11::VISIBLE,
+            "--delay" => delay = args.next().and_then(|d| d.parse().ok()).unwrap_or(delay),
+            "--max" => max = args.next().and_then(|m| m.parse().ok()).unwrap_or(max),
+            "--gif" => gif = args.next(),
+            _ => path = arg,
+        }
+    }
+
+    let input = fs::read_to_string(&path).unwrap_or_else(|e| {
+        eprintln!("{}: {}", path, e);
+        process::exit(1);
+    });
+    let animation = Animation::new(&input, &rules, max).unwrap_or_else(|e| {
+        eprintln!("{}: {}", path, e);
+        process::exit(1);
+    });
+    let result = match gif {
+        Some(file) => fs::File::create(&file)
+            .and_then(|f| animation.write_gif(io::BufWriter::new(f), 4, (delay / 10) as u16))
+            .map(|_| println!("{}: {} frames", file, animation.len())),
+        None => animation.play(io::stdout(), Duration::from_millis(delay)),
+    };
+    if let Err(e) = result {
+        eprintln!("{}", e);
+        process::exit(1);
+    }
+    println!("{}: {}", path, animation.outcome());
+}
@@ -0,0 +1,54 @@
+/**
+ * Contents of a day 7 bag, as a text tree, JSON or a Graphviz graph
+ *
+ * cargo run --example day7_explain -- [rules] [--bag <name>] [--json | --dot]
+ */
+extern crate aoc_2020;
+
+use std::env;
+use std::fs;
+use std::process;
+use aoc_2020::day7::BagRules;
+use aoc_2020::day7::explain::MAX_TREE_NODES;
+
+enum Format {
+    Text,
+    Json,
+    Dot,
+}
+
+fn main() {
+    let mut path = String::from("input/2020/day7.txt");
+    let mut bag = String::from("shiny gold");
+    let mut format = Format::Text;
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--bag" => bag = args.next().unwrap_or(bag),
+            "--json" => format = Format::Json,
+            "--dot" => format = Format::Dot,
+            _ => path = arg,
+        }
+    }
+
+    let input = fs::read_to_string(&path).unwrap_or_else(|e| {
+        eprintln!("{}: {}", path, e);
+        process::exit(1);
+    });
+    let rules: BagRules = input.parse().unwrap_or_else(|e| {
+        eprintln!("{}: {}", path, e);
+        process::exit(1);
+    });
+    let output = match format {
+        Format::Text => rules.contents_tree(&bag, MAX_TREE_NODES).map(|tree| tree.text()),
+        Format::Json => rules.contents_tree(&bag, MAX_TREE_NODES).map(|tree| tree.json() + "\n"),
+        Format::Dot => rules.dot(&bag),
+    };
+    match output {
+        Ok(output) => print!("{}", output),
+        Err(e) => {
+            eprintln!("{}: {}", path, e);
+            process::exit(1);
+        },
+    }
+}
@@ -0,0 +1,59 @@
+/**
+ * Check a day 7 rule set, and compare it to another version
+ *
+ * cargo run --example day7_lint -- [rules] [--bag <name>] [--diff <old rules>] [--unreachable]
+ *
+ * Most bags of a puzzle input play no part in the questions about one bag:
+ * the unreachable bags are only listed on demand.
+ */
+extern crate aoc_2020;
+
+use std::env;
+use std::fs;
+use std::process;
+use aoc_2020::day7::BagRules;
+use aoc_2020::day7::lint::{self, Lint};
+
+fn load(path: &str) -> BagRules {
+    let input = fs::read_to_string(path).unwrap_or_else(|e| {
+        eprintln!("{}: {}", path, e);
+        process::exit(1);
+    });
+    // Not the generator of the solvers: it rejects the duplicate rules
+    input.parse().unwrap_or_else(|e| {
+        eprintln!("{}: {}", path, e);
+        process::exit(1);
+    })
+}
+
+fn main() {
+    let mut path = String::from("input/2020/day7.txt");
+    let mut bag = String::from("shiny gold");
+    let mut old: Option<String> = None;
+    let mut unreachable = false;
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--bag" => bag = args.next().unwrap_or(bag),
+            "--diff" => old = args.next(),
+            "--unreachable" => unreachable = true,
+            _ => path = arg,
+        }
+    }
+
+    let rules = load(&path);
+    if let Some(old) = old {
+        for change in lint::diff(&load(&old), &rules) {
+            println!("{}", change);
+        }
+    }
+    let lints: Vec<Lint> = rules.lints(&bag).into_iter()
+        .filter(|l| unreachable || !matches!(l, Lint::Unreachable{..}))
+        .collect();
+    for problem in lints.iter() {
+        println!("{}: {}", path, problem);
+    }
+    if !lints.is_empty() {
+        process::exit(1);
+    }
+}
@@ -0,0 +1,25 @@
+/**
+ * Control flow graph of a day 8 program, in the Graphviz format
+ *
+ * cargo run --example day8_cfg -- [program] | dot -Tsvg > day8.svg
+ */
+extern crate aoc_2020;
+
+use std::env;
+use std::fs;
+use std::process;
+use aoc_2020::console::Operation;
+use aoc_2020::{day8, error};
+
+fn main() {
+    let path = env::args().nth(1).unwrap_or_else(|| String::from("input/2020/day8.txt"));
+    let input = fs::read_to_string(&path).unwrap_or_else(|e| {
+        eprintln!("{}: {}", path, e);
+        process::exit(1);
+    });
+    let program: Vec<Operation> = error::parse_lines(&input, str::parse).unwrap_or_else(|e| {
+        eprintln!("{}: {}", path, e);
+        process::exit(1);
+    });
+    print!("{}", day8::control_flow(&program));
+}
@@ -0,0 +1,48 @@
+/**
+ * Interactive debugger for day 8 programs
+ *
+ * cargo run --example day8_debugger -- [program] [--flip <index>]
+ */
+extern crate aoc_2020;
+
+use std::env;
+use std::fs;
+use std::io;
+use std::process;
+use aoc_2020::console::Operation;
+use aoc_2020::console::debugger::Debugger;
+use aoc_2020::error;
+
+fn main() {
+    let mut path = String::from("input/2020/day8.txt");
+    let mut flip: Option<usize> = None;
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--flip" => flip = args.next().and_then(|i| i.parse().ok()),
+            _ => path = arg,
+        }
+    }
+
+    let input = fs::read_to_string(&path).unwrap_or_else(|e| {
+        eprintln!("{}: {}", path, e);
+        process::exit(1);
+    });
+    let mut program: Vec<Operation> = error::parse_lines(&input, str::parse).unwrap_or_else(|e| {
+        eprintln!("{}: {}", path, e);
+        process::exit(1);
+    });
+    if let Some(index) = flip {
+        match program.get(index) {
+            Some(op) => program[index] = op.flipped(),
+            None => eprintln!("--flip {}: no such instruction", index),
+        }
+    }
+
+    println!("{} instructions loaded, `help` for the commands", program.len());
+    let mut debugger = Debugger::new(&program);
+    let stdin = io::stdin();
+    if let Err(e) = debugger.repl(stdin.lock(), io::stdout()) {
+        eprintln!("{}", e);
+    }
+}
@@ -0,0 +1,330 @@
+/**
+ * Handheld game console - interpreter for the day 8 boot code
+ */
+use std::fmt;
+use std::str::FromStr;
+use parse_display::Display;
+use crate::error::{self, ParseError, ParseErrorKind};
+
+pub mod asm;
+pub mod cfg;
+pub mod debugger;
+
+/** The instruction set comes from day 8 */
+const DAY: u32 = 8;
+
+// ---------------------------------------------------------------------------
+// Data types
+// ---------------------------------------------------------------------------
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Operation {
+    Nop(i32),
+    Acc(i32),
+    Jmp(i32),
+}
+
+/** Why the machine stopped */
+#[derive(Clone, Copy, Debug, Display, PartialEq, Eq)]
+pub enum Halt {
+    /** About to execute an instruction a second time */
+    #[display("Loop")]
+    Loop,
+    /** About to execute the instruction right after the last one */
+    #[display("Terminated")]
+    Terminated,
+    /** A jump lands further than right after the last instruction */
+    #[display("Out of bounds")]
+    OutOfBounds,
+    /** A jump lands before the first instruction */
+    #[display("Negative PC")]
+    NegativePc,
+}
+
+/** Registers of the machine, saved before each executed instruction */
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct State {
+    pub pc: usize,
+    pub acc: i64,
+}
+
+/**
+ * Executes a program, one instruction at a time.
+ * Faulty jumps are not executed: the PC stays on the faulty instruction.
+ */
+#[derive(Clone, Debug)]
+pub struct Machine<'a> {
+    program: &'a [Operation],
+    /** Program Counter - index in the instruction list */
+    pc: usize,
+    /** Accumulator */
+    acc: i64,
+    /** Instructions already executed */
+    visited: Vec<bool>,
+    /** Registers before each executed instruction */
+    history: Vec<State>,
+}
+
+// ---------------------------------------------------------------------------
+// Implementations
+// ---------------------------------------------------------------------------
+/** "jmp -4": opcode, a single space and a signed offset */
+impl FromStr for Operation {
+    type Err = ParseError;
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let (opcode, arg) = input.split_at(input.find(' ').unwrap_or(input.len()));
+        let value = error::parse_number(DAY, arg.trim_start_matches(' '))
+            .map_err(|e| e.at_column(opcode.len() + 2));
+        match opcode {
+            "nop" => Ok(Operation::Nop(value?)),
+            "acc" => Ok(Operation::Acc(value?)),
+            "jmp" => Ok(Operation::Jmp(value?)),
+            _ => Err(ParseError::new(DAY, ParseErrorKind::UnknownOpcode(String::from(opcode)))),
+        }
+    }
+}
+
+/** Same format as the input, with an explicit sign: "nop +0" */
+impl fmt::Display for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Operation::Nop(value) => write!(f, "nop {:+}", value),
+            Operation::Acc(value) => write!(f, "acc {:+}", value),
+            Operation::Jmp(value) => write!(f, "jmp {:+}", value),
+        }
+    }
+}
+
+impl Operation {
+    /** Swap jmp and nop, acc is left unchanged */
+    pub fn flipped(self) -> Operation {
+        match self {
+            Operation::Nop(value) => Operation::Jmp(value),
+            Operation::Jmp(value) => Operation::Nop(value),
+            Operation::Acc(value) => Operation::Acc(value),
+        }
+    }
+
+    /** Index of the instruction executed after this one at `pc`, if in [0, len] */
+    pub fn successor(self, pc: usize, len: usize) -> Option<usize> {
+        let next = match self {
+            Operation::Jmp(value) => pc as i64 + i64::from(value),
+            _ => pc as i64 + 1,
+        };
+        if next >= 0 && next <= len as i64 { Some(next as usize) } else { None }
+    }
+}
+
+impl<'a> Machine<'a> {
+    pub fn new(program: &'a [Operation]) -> Self {
+        Machine{program, pc: 0, acc: 0, visited: vec![false; program.len()], history: Vec::new()}
+    }
+
+    pub fn program(&self) -> &'a [Operation] {
+        self.program
+    }
+
+    pub fn pc(&self) -> usize {
+        self.pc
+    }
+
+    pub fn acc(&self) -> i64 {
+        self.acc
+    }
+
+    pub fn state(&self) -> State {
+        State{pc: self.pc, acc: self.acc}
+    }
+
+    /** Number of instructions executed so far */
+    pub fn steps(&self) -> usize {
+        self.history.len()
+    }
+
+    /** Registers before each executed instruction, oldest first */
+    pub fn history(&self) -> &[State] {
+        &self.history
+    }
+
+    /** Instruction about to be executed, if any */
+    pub fn current(&self) -> Option<Operation> {
+        self.program.get(self.pc).copied()
+    }
+
+    /** Reason why the next instruction cannot be fetched */
+    fn fetch_halt(&self) -> Option<Halt> {
+        if self.pc == self.program.len() {
+            Some(Halt::Terminated)
+        } else if self.pc > self.program.len() {
+            Some(Halt::OutOfBounds)
+        } else if self.visited[self.pc] {
+            Some(Halt::Loop)
+        } else {
+            None
+        }
+    }
+
+    /** Execute one instruction, or tell why the machine cannot go on */
+    pub fn step(&mut self) -> Result<(), Halt> {
+        if let Some(halt) = self.fetch_halt() {
+            return Err(halt);
+        }
+
+        let (next, acc) = match self.program[self.pc] {
+            Operation::Nop(_) => (self.pc as i64 + 1, self.acc),
+            Operation::Acc(value) => (self.pc as i64 + 1, self.acc + i64::from(value)),
+            Operation::Jmp(value) => (self.pc as i64 + i64::from(value), self.acc),
+        };
+        if next < 0 {
+            return Err(Halt::NegativePc);
+        }
+        if next > self.program.len() as i64 {
+            return Err(Halt::OutOfBounds);
+        }
+
+        trace!(Trace, "{} {} | acc {} -> {}", self.pc, self.program[self.pc], self.acc, acc);
+        self.history.push(self.state());
+        self.visited[self.pc] = true;
+        self.pc = next as usize;
+        self.acc = acc;
+        Ok(())
+    }
+
+    /** Undo the last executed instruction. Return false if there is none */
+    pub fn unstep(&mut self) -> bool {
+        match self.history.pop() {
+            Some(state) => {
+                self.visited[state.pc] = false;
+                self.pc = state.pc;
+                self.acc = state.acc;
+                true
+            },
+            None => false,
+        }
+    }
+
+    /** Run until the machine halts */
+    pub fn run(&mut self) -> Halt {
+        loop {
+            if let Err(halt) = self.step() {
+                trace!(Debug, "{} at pc {} after {} steps, acc {}", halt, self.pc, self.steps(), self.acc);
+                return halt;
+            }
+        }
+    }
+
+    /**
+     * Run until the predicate holds before executing an instruction.
+     * Return None if the predicate stopped the machine, the halt reason otherwise.
+     */
+    pub fn run_until<F>(&mut self, mut predicate: F) -> Option<Halt>
+        where F: FnMut(&Machine) -> bool
+    {
+        loop {
+            if predicate(self) {
+                return None;
+            }
+            if let Err(halt) = self.step() {
+                return Some(halt);
+            }
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+// ---------------------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn program(input: &str) -> Vec<Operation> {
+        error::parse_lines(input, str::parse).unwrap()
+    }
+
+    #[test]
+    fn check_parse() {
+        assert_eq!("jmp -4".parse(), Ok(Operation::Jmp(-4)));
+        assert_eq!("acc +3".parse(), Ok(Operation::Acc(3)));
+        assert_eq!(format!("{}", Operation::Nop(-2)), "nop -2");
+        assert_eq!(format!("{}", Operation::Jmp(0)), "jmp +0");
+        let err = "jnz +4".parse::<Operation>().unwrap_err();
+        assert_eq!(err.kind, ParseErrorKind::UnknownOpcode(String::from("jnz")));
+    }
+
+    #[test]
+    fn check_step() {
+        let code = program("acc +2\nnop +0\njmp -2");
+        let mut machine = Machine::new(&code);
+        assert_eq!(machine.step(), Ok(()));
+        assert_eq!(machine.state(), State{pc: 1, acc: 2});
+        assert_eq!(machine.step(), Ok(()));
+        assert_eq!(machine.step(), Ok(()));
+        assert_eq!(machine.pc(), 0);
+        assert_eq!(machine.step(), Err(Halt::Loop));
+        assert_eq!(machine.steps(), 3);
+        assert_eq!(machine.history()[2], State{pc: 2, acc: 2});
+    }
+
+    #[test]
+    fn check_halts() {
+        let code = program("acc +1\nacc +2");
+        let mut machine = Machine::new(&code);
+        assert_eq!(machine.run(), Halt::Terminated);
+        assert_eq!(machine.acc(), 3);
+
+        // Faulty jumps are not executed
+        let code = program("acc +1\njmp -2");
+        let mut machine = Machine::new(&code);
+        assert_eq!(machine.run(), Halt::NegativePc);
+        assert_eq!(machine.state(), State{pc: 1, acc: 1});
+        assert_eq!(machine.step(), Err(Halt::NegativePc));
+
+        let code = program("jmp +2");
+        assert_eq!(Machine::new(&code).run(), Halt::OutOfBounds);
+        let code = program("jmp +1");
+        assert_eq!(Machine::new(&code).run(), Halt::Terminated);
+        assert_eq!(Machine::new(&[]).run(), Halt::Terminated);
+    }
+
+    #[test]
+    fn check_unstep() {
+        let code = program("acc +2\njmp +2\nacc +5\nacc -1");
+        let mut machine = Machine::new(&code);
+        assert_eq!(machine.run(), Halt::Terminated);
+        assert_eq!(machine.state(), State{pc: 4, acc: 1});
+        assert!(machine.unstep());
+        assert_eq!(machine.state(), State{pc: 3, acc: 2});
+        assert!(machine.unstep());
+        assert!(machine.unstep());
+        assert_eq!(machine.state(), State{pc: 0, acc: 0});
+        assert!(!machine.unstep());
+        // Instructions can be executed again
+        assert_eq!(machine.run(), Halt::Terminated);
+        assert_eq!(machine.steps(), 3);
+    }
+
+    #[test]
+    fn check_flipped() {
+        assert_eq!(Operation::Jmp(-3).flipped(), Operation::Nop(-3));
+        assert_eq!(Operation::Nop(2).flipped(), Operation::Jmp(2));
+        assert_eq!(Operation::Acc(1).flipped(), Operation::Acc(1));
+    }
+
+    #[test]
+    fn check_successor() {
+        assert_eq!(Operation::Nop(-5).successor(2, 3), Some(3));
+        assert_eq!(Operation::Acc(1).successor(3, 3), None);
+        assert_eq!(Operation::Jmp(-2).successor(2, 3), Some(0));
+        assert_eq!(Operation::Jmp(-3).successor(2, 3), None);
+        assert_eq!(Operation::Jmp(4).successor(0, 3), None);
+    }
+
+    #[test]
+    fn check_run_until() {
+        let code = program("acc +1\nacc +1\nacc +1\njmp -3");
+        let mut machine = Machine::new(&code);
+        assert_eq!(machine.run_until(|m| m.acc() == 2), None);
+        assert_eq!(machine.pc(), 2);
+        assert_eq!(machine.run_until(|m| m.acc() == 10), Some(Halt::Loop));
+        assert_eq!(machine.acc(), 3);
+    }
+}
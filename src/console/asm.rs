@@ -0,0 +1,259 @@
+/**
+ * Assembler and disassembler for console programs
+ *
+ * The assembly language is the puzzle format, plus:
+ *  - comments, from `#` or `;` to the end of the line
+ *  - blank lines
+ *  - labels, `name:` alone on a line or before an instruction
+ *  - label references as jmp/nop argument: `jmp loop_start`
+ *
+ * For instance `start: acc +1 ; count` then `jmp start` on the next line.
+ * A label after the last instruction refers to the end of the program.
+ */
+use std::collections::{BTreeSet, HashMap};
+use std::convert::TryFrom;
+use std::fmt::Write;
+use super::{Operation, DAY};
+use crate::error::{self, ParseError, ParseErrorKind};
+
+// ---------------------------------------------------------------------------
+// Data types
+// ---------------------------------------------------------------------------
+/** Argument of an instruction, before label resolution */
+enum Argument<'a> {
+    Offset(i32),
+    Label(&'a str),
+}
+
+/** Instruction waiting for its labels to be resolved */
+struct Pending<'a> {
+    line: usize,
+    opcode: &'a str,
+    argument: Argument<'a>,
+    /** Column of the argument */
+    column: usize,
+}
+
+// ---------------------------------------------------------------------------
+// Implementations
+// ---------------------------------------------------------------------------
+/** 1-based column of `part` inside `line` (part must be a slice of line) */
+fn column(line: &str, part: &str) -> usize {
+    part.as_ptr() as usize - line.as_ptr() as usize + 1
+}
+
+fn is_label(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars.next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_') &&
+        chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+fn unexpected(expected: &'static str, found: &str) -> ParseError {
+    ParseError::new(DAY, ParseErrorKind::Unexpected{expected, found: String::from(found)})
+}
+
+/** First pass: collect labels and instructions of one line */
+fn parse_line<'a>(line: &'a str, number: usize, labels: &mut HashMap<&'a str, usize>,
+                  code: &mut Vec<Pending<'a>>) -> Result<(), ParseError> {
+    let text = line.split(['#', ';']).next().unwrap_or("");
+    let mut tokens = text.split_whitespace().peekable();
+
+    while let Some(label) = tokens.peek().and_then(|t| t.strip_suffix(':')) {
+        let token = tokens.next().unwrap_or(label);
+        if !is_label(label) {
+            return Err(unexpected("a label name", label).at_column(column(line, token)));
+        }
+        if labels.insert(label, code.len()).is_some() {
+            return Err(ParseError::new(DAY, ParseErrorKind::DuplicateLabel(String::from(label)))
+                .at_column(column(line, token)));
+        }
+    }
+
+    let opcode = match tokens.next() {
+        Some(opcode) => opcode,
+        None => return Ok(()),
+    };
+    if !["nop", "acc", "jmp"].contains(&opcode) {
+        return Err(ParseError::new(DAY, ParseErrorKind::UnknownOpcode(String::from(opcode)))
+            .at_column(column(line, opcode)));
+    }
+    let arg = tokens.next()
+        .ok_or_else(|| unexpected("an offset or a label", "end of line").at_column(line.len() + 1))?;
+    let argument = if opcode != "acc" && is_label(arg) {
+        Argument::Label(arg)
+    } else {
+        Argument::Offset(error::parse_number(DAY, arg).map_err(|e| e.at_column(column(line, arg)))?)
+    };
+    if let Some(extra) = tokens.next() {
+        return Err(unexpected("end of line", extra).at_column(column(line, extra)));
+    }
+
+    code.push(Pending{line: number, opcode, argument, column: column(line, arg)});
+    Ok(())
+}
+
+/** Translate an assembly listing into a program */
+pub fn assemble(source: &str) -> Result<Vec<Operation>, ParseError> {
+    let mut labels = HashMap::new();
+    let mut code = Vec::new();
+    for (index, line) in source.lines().enumerate() {
+        parse_line(line, index + 1, &mut labels, &mut code).map_err(|e| e.at_line(index + 1))?;
+    }
+
+    let mut program = Vec::with_capacity(code.len());
+    for (pc, pending) in code.iter().enumerate() {
+        let value = match pending.argument {
+            Argument::Offset(value) => value,
+            Argument::Label(name) => {
+                let located = |kind| ParseError::new(DAY, kind).at_line(pending.line).at_column(pending.column);
+                let target = labels.get(name)
+                    .ok_or_else(|| located(ParseErrorKind::UnknownLabel(String::from(name))))?;
+                i32::try_from(*target as i64 - pc as i64)
+                    .map_err(|_| located(ParseErrorKind::InvalidNumber(String::from(name))))?
+            },
+        };
+        program.push(match pending.opcode {
+            "nop" => Operation::Nop(value),
+            "acc" => Operation::Acc(value),
+            _ => Operation::Jmp(value),
+        });
+    }
+    Ok(program)
+}
+
+/** Target of a jmp, if it is inside the program or right after it */
+fn jump_target(op: &Operation, pc: usize, len: usize) -> Option<usize> {
+    match op {
+        Operation::Jmp(_) => op.successor(pc, len),
+        _ => None,
+    }
+}
+
+/**
+ * Labelled listing of a program: every jmp target gets a label `L<index>`.
+ * Jumps outside the program and nop arguments are kept as offsets.
+ */
+pub fn disassemble(program: &[Operation]) -> String {
+    let len = program.len();
+    let targets: BTreeSet<usize> = program.iter().enumerate()
+        .filter_map(|(pc, op)| jump_target(op, pc, len))
+        .collect();
+
+    let mut out = String::new();
+    for pc in 0..=len {
+        if targets.contains(&pc) {
+            let _ = writeln!(out, "L{}:", pc);
+        }
+        let op = match program.get(pc) {
+            Some(op) => op,
+            None => break,
+        };
+        let _ = match jump_target(op, pc, len) {
+            Some(target) => writeln!(out, "    jmp L{}", target),
+            None => writeln!(out, "    {}", op),
+        };
+    }
+    out
+}
+
+/** Program in the puzzle format, one instruction per line */
+pub fn format(program: &[Operation]) -> String {
+    program.iter().map(|op| format!("{}\n", op)).collect()
+}
+
+// ---------------------------------------------------------------------------
+// ---------------------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    static INPUT: &str = "nop +0
+acc +1
+jmp +4
+acc +3
+jmp -3
+acc -99
+acc +1
+jmp -4
+acc +6
+";
+
+    static LISTING: &str = "    nop +0
+L1:
+    acc +1
+    jmp L6
+L3:
+    acc +3
+    jmp L1
+    acc -99
+L6:
+    acc +1
+    jmp L3
+    acc +6
+";
+
+    #[test]
+    fn check_assemble() {
+        let source = "
+# Count to 3, then leave
+start:  acc +1      ; one more
+        nop loop
+loop:   acc +2
+        jmp end
+        jmp start   # never reached
+end:
+";
+        let program = assemble(source).unwrap();
+        assert_eq!(program, vec![
+            Operation::Acc(1),
+            Operation::Nop(1),
+            Operation::Acc(2),
+            Operation::Jmp(2),
+            Operation::Jmp(-4),
+        ]);
+    }
+
+    #[test]
+    fn check_assemble_errors() {
+        let err = assemble("a: nop +0\n  jmp b").unwrap_err();
+        assert_eq!(format!("{}", err), "day8 line 2 column 7: unknown label `b`");
+        let err = assemble("a: nop +0\na: jmp a").unwrap_err();
+        assert_eq!(format!("{}", err), "day8 line 2 column 1: duplicate label `a`");
+        let err = assemble("nop +0\n  jnz +1").unwrap_err();
+        assert_eq!(format!("{}", err), "day8 line 2 column 3: unknown opcode `jnz`");
+        let err = assemble("acc start").unwrap_err();
+        assert_eq!(format!("{}", err), "day8 line 1 column 5: invalid number `start`");
+        let err = assemble("jmp +1 +2").unwrap_err();
+        assert_eq!(err.column, Some(8));
+        let err = assemble("jmp").unwrap_err();
+        assert_eq!(err.column, Some(4));
+        let err = assemble("1x: jmp +1").unwrap_err();
+        assert_eq!(format!("{}", err), "day8 line 1 column 1: expected a label name, found `1x`");
+    }
+
+    #[test]
+    fn check_disassemble() {
+        let program = assemble(INPUT).unwrap();
+        assert_eq!(disassemble(&program), LISTING);
+        assert_eq!(assemble(LISTING).unwrap(), program);
+        assert_eq!(format(&program), INPUT);
+
+        // Jump to the end of the program, and out of it
+        let program = vec![Operation::Jmp(2), Operation::Jmp(5)];
+        assert_eq!(disassemble(&program), "    jmp L2\n    jmp +5\nL2:\n");
+    }
+
+    #[test]
+    fn check_round_trip() {
+        let program: Vec<Operation> = (0..200).map(|i: i32| match i % 5 {
+            0 => Operation::Acc(i - 100),
+            1 => Operation::Nop((i * 7) % 31 - 15),
+            2 => Operation::Jmp(0),
+            _ => Operation::Jmp((i * 13) % 401 - 200),
+        }).collect();
+        let listing = disassemble(&program);
+        assert_eq!(assemble(&listing).unwrap(), program);
+        assert_eq!(disassemble(&assemble(&listing).unwrap()), listing);
+        assert_eq!(assemble(&format(&program)).unwrap(), program);
+    }
+}
@@ -0,0 +1,254 @@
+/**
+ * Control flow graph of console programs
+ *
+ * Instructions are grouped in basic blocks: a block starts at the first
+ * instruction, at every jmp target and right after every jmp. An extra empty
+ * block stands for the end of the program, where terminating programs go.
+ * The graph is exported in the Graphviz format with petgraph's Dot.
+ */
+use std::collections::BTreeSet;
+use std::fmt::Write;
+use parse_display::Display;
+use petgraph::dot::{Config, Dot};
+use petgraph::graph::{DiGraph, NodeIndex};
+use petgraph::visit::EdgeRef;
+use super::Operation;
+
+// ---------------------------------------------------------------------------
+// Data types
+// ---------------------------------------------------------------------------
+/** Instructions [start, end) of the program, executed in sequence */
+#[derive(Clone, Copy, Debug, Display, PartialEq, Eq)]
+#[display("{start}..{end}")]
+pub struct Block {
+    pub start: usize,
+    pub end: usize,
+}
+
+#[derive(Clone, Copy, Debug, Display, PartialEq, Eq)]
+pub enum Edge {
+    /** Falling through to the next instruction */
+    #[display("next")]
+    Next,
+    #[display("jmp")]
+    Jump,
+    /** Where the last instruction of the block goes once flipped */
+    #[display("flip")]
+    Flipped,
+}
+
+/** What to show in the exported graph */
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Highlight {
+    /** Instructions of the infinite loop, in execution order */
+    pub looping: Vec<usize>,
+    /** Instruction flipped to repair the program */
+    pub flipped: Option<usize>,
+}
+
+pub struct Cfg<'a> {
+    program: &'a [Operation],
+    graph: DiGraph<Block, Edge>,
+    /** Node of the block holding each instruction, and of the end block */
+    nodes: Vec<NodeIndex>,
+}
+
+// ---------------------------------------------------------------------------
+// Implementations
+// ---------------------------------------------------------------------------
+impl<'a> Cfg<'a> {
+    pub fn new(program: &'a [Operation]) -> Self {
+        Cfg::with_leaders(program, &[])
+    }
+
+    /** Build the graph, also starting blocks at the given instructions */
+    pub fn with_leaders(program: &'a [Operation], leaders: &[usize]) -> Self {
+        let len = program.len();
+        let mut starts: BTreeSet<usize> = leaders.iter().copied().filter(|pc| *pc <= len).collect();
+        starts.insert(0);
+        starts.insert(len);
+        for (pc, op) in program.iter().enumerate() {
+            if let Operation::Jmp(_) = op {
+                starts.insert(pc + 1);
+                starts.extend(op.successor(pc, len));
+            }
+        }
+
+        let mut graph = DiGraph::new();
+        let mut nodes = Vec::with_capacity(len + 1);
+        let bounds: Vec<usize> = starts.iter().copied().collect();
+        for (i, start) in bounds.iter().enumerate() {
+            let end = bounds.get(i + 1).copied().unwrap_or(len);
+            let node = graph.add_node(Block{start: *start, end});
+            nodes.extend((*start..end.max(start + 1)).map(|_| node));
+        }
+
+        for node in graph.node_indices().collect::<Vec<_>>() {
+            let block = graph[node];
+            if block.start == len {
+                continue;
+            }
+            let pc = block.end - 1;
+            let edge = match program[pc] {
+                Operation::Jmp(_) => Edge::Jump,
+                _ => Edge::Next,
+            };
+            if let Some(next) = program[pc].successor(pc, len) {
+                graph.add_edge(node, nodes[next], edge);
+            }
+        }
+        trace!(Debug, "{} instructions, {} blocks", len, graph.node_count());
+        Cfg{program, graph, nodes}
+    }
+
+    pub fn graph(&self) -> &DiGraph<Block, Edge> {
+        &self.graph
+    }
+
+    /** Blocks in program order, the end block last */
+    pub fn blocks(&self) -> impl Iterator<Item = &Block> {
+        self.graph.raw_nodes().iter().map(|n| &n.weight)
+    }
+
+    /** Block holding an instruction (len is the end block) */
+    pub fn block_of(&self, pc: usize) -> Option<Block> {
+        self.nodes.get(pc).map(|n| self.graph[*n])
+    }
+
+    /** Instruction listing of a block, as a left-justified Dot label */
+    fn label(&self, block: &Block, flipped: Option<usize>) -> String {
+        if block.start == self.program.len() {
+            return String::from("end");
+        }
+        let mut label = String::new();
+        for pc in block.start..block.end {
+            let mark = if flipped == Some(pc) { "*" } else { " " };
+            let _ = write!(label, "{}{:>4}  {}\\l", mark, pc, self.program[pc]);
+        }
+        label
+    }
+
+    /**
+     * Graphviz export. Blocks and edges of the loop are red, the block of the
+     * flipped instruction is filled and its new destination is a dashed edge.
+     */
+    pub fn dot(&self, highlight: &Highlight) -> String {
+        let len = self.program.len();
+        let looping: BTreeSet<NodeIndex> = highlight.looping.iter().map(|pc| self.nodes[*pc]).collect();
+        // Pairs of consecutive loop instructions in different blocks
+        let mut loop_edges = BTreeSet::new();
+        let next_loop = highlight.looping.iter().skip(1).chain(highlight.looping.first());
+        for (from, to) in highlight.looping.iter().zip(next_loop) {
+            if self.nodes[*from] != self.nodes[*to] || highlight.looping.len() == 1 {
+                loop_edges.insert((self.nodes[*from], self.nodes[*to]));
+            }
+        }
+
+        let mut graph = self.graph.clone();
+        let flipped = highlight.flipped.filter(|pc| *pc < len);
+        if let Some(pc) = flipped {
+            if let Some(next) = self.program[pc].flipped().successor(pc, len) {
+                graph.add_edge(self.nodes[pc], self.nodes[next], Edge::Flipped);
+            }
+        }
+
+        let node_attr = |_, (node, block): (NodeIndex, &Block)| {
+            let mut attr = format!("shape = box, fontname = monospace, label = \"{}\"", self.label(block, flipped));
+            if looping.contains(&node) {
+                attr.push_str(", color = red");
+            }
+            if flipped.is_some_and(|pc| self.nodes[pc] == node) {
+                attr.push_str(", style = filled, fillcolor = yellow");
+            }
+            attr
+        };
+        let edge_attr = |_, edge: petgraph::graph::EdgeReference<Edge>| {
+            let mut attr = format!("label = \"{}\"", edge.weight());
+            if *edge.weight() == Edge::Flipped {
+                attr.push_str(", style = dashed, color = darkgreen");
+            } else if loop_edges.contains(&(edge.source(), edge.target())) {
+                attr.push_str(", color = red");
+            }
+            attr
+        };
+        let config = [Config::NodeNoLabel, Config::EdgeNoLabel];
+        format!("{}", Dot::with_attr_getters(&graph, &config, &edge_attr, &node_attr))
+    }
+}
+
+// ---------------------------------------------------------------------------
+// ---------------------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::console::asm;
+
+    static INPUT: &str = "nop +0
+acc +1
+jmp +4
+acc +3
+jmp -3
+acc -99
+acc +1
+jmp -4
+acc +6";
+
+    fn blocks(cfg: &Cfg) -> Vec<(usize, usize)> {
+        cfg.blocks().map(|b| (b.start, b.end)).collect()
+    }
+
+    #[test]
+    fn check_blocks() {
+        let program = asm::assemble(INPUT).unwrap();
+        let cfg = Cfg::new(&program);
+        assert_eq!(blocks(&cfg), vec![(0, 1), (1, 3), (3, 5), (5, 6), (6, 8), (8, 9), (9, 9)]);
+        assert_eq!(cfg.block_of(4), Some(Block{start: 3, end: 5}));
+        assert_eq!(cfg.block_of(9), Some(Block{start: 9, end: 9}));
+        assert_eq!(cfg.block_of(10), None);
+
+        let edges: Vec<(usize, usize, Edge)> = cfg.graph().edge_references()
+            .map(|e| (cfg.graph()[e.source()].start, cfg.graph()[e.target()].start, *e.weight()))
+            .collect();
+        assert_eq!(edges, vec![
+            (0, 1, Edge::Next),
+            (1, 6, Edge::Jump),
+            (3, 1, Edge::Jump),
+            (5, 6, Edge::Next),
+            (6, 3, Edge::Jump),
+            (8, 9, Edge::Next),
+        ]);
+
+        // Out of bounds jumps have no edge
+        let program = asm::assemble("acc +1\njmp -5").unwrap();
+        let cfg = Cfg::new(&program);
+        assert_eq!(blocks(&cfg), vec![(0, 2), (2, 2)]);
+        assert_eq!(cfg.graph().edge_count(), 0);
+
+        let cfg = Cfg::new(&[]);
+        assert_eq!(blocks(&cfg), vec![(0, 0)]);
+    }
+
+    #[test]
+    fn check_leaders() {
+        let program = asm::assemble(INPUT).unwrap();
+        let cfg = Cfg::with_leaders(&program, &[2, 7, 42]);
+        assert_eq!(blocks(&cfg), vec![(0, 1), (1, 2), (2, 3), (3, 5), (5, 6), (6, 7), (7, 8), (8, 9), (9, 9)]);
+    }
+
+    #[test]
+    fn check_dot() {
+        let program = asm::assemble(INPUT).unwrap();
+        let cfg = Cfg::with_leaders(&program, &[7]);
+        let dot = cfg.dot(&Highlight{looping: vec![1, 2, 6, 7, 3, 4], flipped: Some(7)});
+        assert!(dot.starts_with("digraph {\n"));
+        assert!(dot.contains("label = \"    1  acc +1\\l    2  jmp +4\\l\", color = red]"));
+        assert!(dot.contains("label = \"*   7  jmp -4\\l\", color = red, style = filled, fillcolor = yellow]"));
+        assert!(dot.contains("label = \"end\"]"));
+        assert!(dot.contains("1 -> 4 [ label = \"jmp\", color = red]"));
+        assert!(dot.contains("4 -> 5 [ label = \"next\", color = red]"));
+        assert!(dot.contains("5 -> 2 [ label = \"jmp\", color = red]"));
+        assert!(dot.contains("5 -> 6 [ label = \"flip\", style = dashed, color = darkgreen]"));
+        // Not part of the loop
+        assert!(dot.contains("0 -> 1 [ label = \"next\"]"));
+    }
+}
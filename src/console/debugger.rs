@@ -0,0 +1,372 @@
+/**
+ * Step debugger for console programs
+ *
+ * Breakpoints stop before executing an instruction, watchpoints stop after
+ * an instruction changed the accumulator. The machine history allows going
+ * back in time one instruction at a time.
+ */
+use std::collections::BTreeSet;
+use std::fmt::Write as _;
+use std::io::{self, BufRead, Write};
+use parse_display::Display;
+use super::{Halt, Machine, Operation};
+
+// ---------------------------------------------------------------------------
+// Data types
+// ---------------------------------------------------------------------------
+/** Condition on the accumulator */
+#[derive(Clone, Copy, Debug, Display, PartialEq, Eq)]
+pub enum Watch {
+    #[display("acc changes")]
+    Change,
+    #[display("acc == {0}")]
+    Equals(i64),
+}
+
+/** Why the debugger gave control back */
+#[derive(Clone, Copy, Debug, Display, PartialEq, Eq)]
+pub enum Stop {
+    /** Requested number of steps executed */
+    #[display("Stepped")]
+    Stepped,
+    #[display("Breakpoint at {0}")]
+    Breakpoint(usize),
+    #[display("Watchpoint `{0}`: acc {1} -> {2}")]
+    Watchpoint(Watch, i64, i64),
+    #[display("Halted: {0}")]
+    Halted(Halt),
+    /** Reverse step at the start of the program */
+    #[display("At start of program")]
+    Start,
+}
+
+/** Commands of the interactive mode */
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Command {
+    Step(usize),
+    Back(usize),
+    Continue,
+    Break(usize),
+    Delete(usize),
+    Watch(Watch),
+    Unwatch,
+    Dump,
+    List,
+    Help,
+    Quit,
+}
+
+pub struct Debugger<'a> {
+    machine: Machine<'a>,
+    breakpoints: BTreeSet<usize>,
+    watchpoints: Vec<Watch>,
+}
+
+static HELP: &str = "\
+s, step [n]      execute n instructions (1)
+b, back [n]      undo n instructions (1)
+c, continue      run until a breakpoint, a watchpoint or a halt
+break <pc>       stop before executing instruction pc
+delete <pc>      remove the breakpoint at pc
+watch [value]    stop when acc changes, or when it reaches value
+unwatch          remove all watchpoints
+d, dump          show registers and breakpoints
+l, list          show the code around pc
+q, quit          leave the debugger";
+
+// ---------------------------------------------------------------------------
+// Implementations
+// ---------------------------------------------------------------------------
+impl Command {
+    pub fn parse(input: &str) -> Result<Command, String> {
+        let mut words = input.split_whitespace();
+        let name = words.next().unwrap_or("");
+        let arg = words.next();
+        let number = |default: Option<usize>| match arg {
+            Some(n) => n.parse::<usize>().map_err(|_| format!("invalid number `{}`", n)),
+            None => default.ok_or_else(|| format!("`{}` needs an argument", name)),
+        };
+        match name {
+            "s" | "step" => Ok(Command::Step(number(Some(1))?)),
+            "b" | "back" => Ok(Command::Back(number(Some(1))?)),
+            "c" | "continue" => Ok(Command::Continue),
+            "break" => Ok(Command::Break(number(None)?)),
+            "delete" => Ok(Command::Delete(number(None)?)),
+            "watch" => match arg {
+                None => Ok(Command::Watch(Watch::Change)),
+                Some(n) => n.parse().map(|v| Command::Watch(Watch::Equals(v)))
+                    .map_err(|_| format!("invalid number `{}`", n)),
+            },
+            "unwatch" => Ok(Command::Unwatch),
+            "d" | "dump" => Ok(Command::Dump),
+            "l" | "list" => Ok(Command::List),
+            "h" | "help" => Ok(Command::Help),
+            "q" | "quit" => Ok(Command::Quit),
+            _ => Err(format!("unknown command `{}`, try `help`", name)),
+        }
+    }
+}
+
+impl Watch {
+    fn triggered(&self, before: i64, after: i64) -> bool {
+        match self {
+            Watch::Change => before != after,
+            Watch::Equals(value) => before != after && after == *value,
+        }
+    }
+}
+
+impl<'a> Debugger<'a> {
+    pub fn new(program: &'a [Operation]) -> Self {
+        Debugger{machine: Machine::new(program), breakpoints: BTreeSet::new(), watchpoints: Vec::new()}
+    }
+
+    pub fn machine(&self) -> &Machine<'a> {
+        &self.machine
+    }
+
+    pub fn set_breakpoint(&mut self, pc: usize) {
+        self.breakpoints.insert(pc);
+    }
+
+    pub fn remove_breakpoint(&mut self, pc: usize) -> bool {
+        self.breakpoints.remove(&pc)
+    }
+
+    pub fn watch(&mut self, watch: Watch) {
+        if !self.watchpoints.contains(&watch) {
+            self.watchpoints.push(watch);
+        }
+    }
+
+    pub fn unwatch(&mut self) {
+        self.watchpoints.clear();
+    }
+
+    /** Execute one instruction, checking the watchpoints */
+    fn single_step(&mut self) -> Option<Stop> {
+        let before = self.machine.acc();
+        if let Err(halt) = self.machine.step() {
+            return Some(Stop::Halted(halt));
+        }
+        let after = self.machine.acc();
+        self.watchpoints.iter()
+            .find(|w| w.triggered(before, after))
+            .map(|w| Stop::Watchpoint(*w, before, after))
+    }
+
+    /** Execute up to n instructions, stopping early on a halt or a watchpoint */
+    pub fn step(&mut self, n: usize) -> Stop {
+        for _ in 0..n {
+            if let Some(stop) = self.single_step() {
+                return stop;
+            }
+        }
+        Stop::Stepped
+    }
+
+    /** Undo up to n instructions */
+    pub fn back(&mut self, n: usize) -> Stop {
+        for _ in 0..n {
+            if !self.machine.unstep() {
+                return Stop::Start;
+            }
+        }
+        Stop::Stepped
+    }
+
+    /** Run until a breakpoint, a watchpoint or a halt.
+     * A breakpoint on the current instruction does not stop immediately. */
+    pub fn resume(&mut self) -> Stop {
+        loop {
+            if let Some(stop) = self.single_step() {
+                return stop;
+            }
+            if self.breakpoints.contains(&self.machine.pc()) {
+                return Stop::Breakpoint(self.machine.pc());
+            }
+        }
+    }
+
+    /** Registers, execution count and breakpoints */
+    pub fn dump(&self) -> String {
+        let mut out = String::new();
+        let current = self.machine.current().map_or(String::from("-"), |op| op.to_string());
+        let _ = writeln!(out, "pc {} ({})  acc {}  steps {}",
+                         self.machine.pc(), current, self.machine.acc(), self.machine.steps());
+        let breaks: Vec<String> = self.breakpoints.iter().map(|b| b.to_string()).collect();
+        let _ = writeln!(out, "breakpoints: [{}]", breaks.join(", "));
+        let watches: Vec<String> = self.watchpoints.iter().map(|w| w.to_string()).collect();
+        let _ = write!(out, "watchpoints: [{}]", watches.join(", "));
+        out
+    }
+
+    /** Code around the current instruction, `*` marks breakpoints */
+    pub fn listing(&self, context: usize) -> String {
+        let program = self.machine.program();
+        let pc = self.machine.pc();
+        let start = pc.saturating_sub(context);
+        let end = (pc + context + 1).min(program.len());
+        let mut out = String::new();
+        for (index, op) in program.iter().enumerate().take(end).skip(start) {
+            let marker = if index == pc { "=>" } else { "  " };
+            let brk = if self.breakpoints.contains(&index) { '*' } else { ' ' };
+            let _ = writeln!(out, "{}{}{:5}  {}", marker, brk, index, op);
+        }
+        if pc >= program.len() {
+            let _ = writeln!(out, "=> {:5}  <end of program>", pc);
+        }
+        out.trim_end().to_string()
+    }
+
+    /** Apply a command, return the text to display */
+    pub fn execute(&mut self, command: Command) -> String {
+        match command {
+            Command::Step(n) => { let stop = self.step(n); self.report(stop) },
+            Command::Back(n) => { let stop = self.back(n); self.report(stop) },
+            Command::Continue => { let stop = self.resume(); self.report(stop) },
+            Command::Break(pc) => {
+                self.set_breakpoint(pc);
+                format!("Breakpoint set at {}", pc)
+            },
+            Command::Delete(pc) => match self.remove_breakpoint(pc) {
+                true => format!("Breakpoint at {} removed", pc),
+                false => format!("No breakpoint at {}", pc),
+            },
+            Command::Watch(watch) => {
+                self.watch(watch);
+                format!("Watching `{}`", watch)
+            },
+            Command::Unwatch => { self.unwatch(); String::from("Watchpoints removed") },
+            Command::Dump => self.dump(),
+            Command::List => self.listing(3),
+            Command::Help => String::from(HELP),
+            Command::Quit => String::new(),
+        }
+    }
+
+    fn report(&self, stop: Stop) -> String {
+        format!("{}\n{}", stop, self.listing(0))
+    }
+
+    /**
+     * Interactive mode: read commands from input until `quit` or end of input.
+     * An empty line repeats the last command.
+     */
+    pub fn repl<R: BufRead, W: Write>(&mut self, input: R, mut output: W) -> io::Result<()> {
+        let mut last: Option<Command> = None;
+        writeln!(output, "{}", self.listing(0))?;
+        write!(output, "(dbg) ")?;
+        output.flush()?;
+        for line in input.lines() {
+            let line = line?;
+            let command = if line.trim().is_empty() {
+                last.ok_or_else(|| String::from("no previous command"))
+            } else {
+                Command::parse(&line)
+            };
+            match command {
+                Ok(Command::Quit) => return Ok(()),
+                Ok(command) => {
+                    writeln!(output, "{}", self.execute(command))?;
+                    last = Some(command);
+                },
+                Err(message) => writeln!(output, "{}", message)?,
+            }
+            write!(output, "(dbg) ")?;
+            output.flush()?;
+        }
+        writeln!(output)
+    }
+}
+
+// ---------------------------------------------------------------------------
+// ---------------------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error;
+
+    static INPUT: &str = "nop +0
+acc +1
+jmp +4
+acc +3
+jmp -3
+acc -99
+acc +1
+jmp -4
+acc +6";
+
+    fn program(input: &str) -> Vec<Operation> {
+        error::parse_lines(input, str::parse).unwrap()
+    }
+
+    #[test]
+    fn check_command() {
+        assert_eq!(Command::parse("s"), Ok(Command::Step(1)));
+        assert_eq!(Command::parse("step 10"), Ok(Command::Step(10)));
+        assert_eq!(Command::parse("break 7"), Ok(Command::Break(7)));
+        assert_eq!(Command::parse("watch -5"), Ok(Command::Watch(Watch::Equals(-5))));
+        assert_eq!(Command::parse("watch"), Ok(Command::Watch(Watch::Change)));
+        assert!(Command::parse("break").is_err());
+        assert!(Command::parse("step x").is_err());
+        assert!(Command::parse("jump").is_err());
+    }
+
+    #[test]
+    fn check_breakpoint() {
+        let code = program(INPUT);
+        let mut dbg = Debugger::new(&code);
+        dbg.set_breakpoint(6);
+        dbg.set_breakpoint(3);
+        assert_eq!(dbg.resume(), Stop::Breakpoint(6));
+        assert_eq!(dbg.machine().acc(), 1);
+        assert_eq!(dbg.resume(), Stop::Breakpoint(3));
+        assert_eq!(dbg.machine().acc(), 2);
+        assert_eq!(dbg.resume(), Stop::Halted(Halt::Loop));
+        assert_eq!(dbg.machine().acc(), 5);
+    }
+
+    #[test]
+    fn check_watchpoint() {
+        let code = program(INPUT);
+        let mut dbg = Debugger::new(&code);
+        dbg.watch(Watch::Equals(2));
+        assert_eq!(dbg.resume(), Stop::Watchpoint(Watch::Equals(2), 1, 2));
+        assert_eq!(dbg.machine().pc(), 7);
+        dbg.unwatch();
+        dbg.watch(Watch::Change);
+        assert_eq!(dbg.resume(), Stop::Watchpoint(Watch::Change, 2, 5));
+    }
+
+    #[test]
+    fn check_step_back() {
+        let code = program(INPUT);
+        let mut dbg = Debugger::new(&code);
+        assert_eq!(dbg.step(3), Stop::Stepped);
+        assert_eq!(dbg.machine().pc(), 6);
+        assert_eq!(dbg.back(1), Stop::Stepped);
+        assert_eq!(dbg.machine().pc(), 2);
+        assert_eq!(dbg.machine().acc(), 1);
+        assert_eq!(dbg.back(5), Stop::Start);
+        assert_eq!(dbg.machine().pc(), 0);
+        assert_eq!(dbg.step(100), Stop::Halted(Halt::Loop));
+        assert_eq!(dbg.machine().steps(), 7);
+    }
+
+    #[test]
+    fn check_repl() {
+        let code = program(INPUT);
+        let mut dbg = Debugger::new(&code);
+        let script = "break 4\nc\nd\n\nback 2\nfoo\nq\nstep\n";
+        let mut output = Vec::new();
+        dbg.repl(script.as_bytes(), &mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.contains("Breakpoint set at 4"));
+        assert!(output.contains("Breakpoint at 4\n=>*    4  jmp -3"));
+        assert!(output.contains("pc 4 (jmp -3)  acc 5  steps 6\nbreakpoints: [4]"));
+        assert!(output.contains("unknown command `foo`"));
+        // Stopped at quit
+        assert_eq!(dbg.machine().steps(), 4);
+    }
+}
@@ -1,91 +1,264 @@
+/**
+ * Day 1 - Report Repair
+ */
+use std::collections::HashMap;
+use crate::error::{self, ParseError};
+
+const DAY: u32 = 1;
+
 /*
  * Split up input into lines
  */
 #[aoc_generator(day1)]
-pub fn input_gen(input: &str) -> Vec<u32>
+pub fn input_gen(input: &str) -> Result<Vec<u32>, ParseError>
 {
-    input.lines().map(|l| { l.parse().unwrap() }).collect()
+    error::parse_lines(input, |l| error::parse_number(DAY, l))
 }
 
 // ---------------------------------------------------------------------------
 
 /*
- * @brief Find two entries that sum to the target
+ * @brief Sorted values and the tables used to complete a combination
+ */
+struct KSum {
+    /** Values sorted in increasing order */
+    sorted: Vec<u64>,
+    /** prefix[i]: sum of the i smallest values */
+    prefix: Vec<u64>,
+    /** Last position of each value */
+    last: HashMap<u64, usize>,
+    /**
+     * Pair (i, j), i < j, of each sum of two values, with the largest i.
+     * Only built for k >= 4, where it saves a level of enumeration.
+     */
+    pairs: Option<HashMap<u64, (usize, usize)>>,
+}
+
+/*
+ * @brief Find k distinct entries that sum to the target
+ *
+ * Entries are sorted once: the first values of the combination are
+ * enumerated in increasing order, the last one (or the last two when k >= 4,
+ * from a table of the sums of pairs) are found with a hash lookup.
+ * Branches are cut as soon as the smallest or the largest values left can no
+ * longer reach the target. O(n log n) for k <= 2, O(n^(k-1)) for k = 3 and
+ * O(n^(k-2)) lookups after an O(n^2) table beyond, in the worst case.
  *
  * @param input:    array of values
- * @param target:   target sum of 2 values of input
+ * @param k:        number of entries to combine
+ * @param target:   target sum of k values of input
  *
- * @return product of the two values, or 0 if not found
+ * @return indices in input of the matching entries, or None if not found
  */
-pub fn solve_2_values(input: &[u32], target: u32) -> u32
+pub fn k_sum_indices(input: &[u32], k: usize, target: u32) -> Option<Vec<usize>>
 {
-    //println!("Solve 2 values, target {}", target);
-    // quadratic solution, bad bad bad
-    for i in input.iter() {
-        let x = target.saturating_sub(*i);
-        if x == 0 { continue }
-
-        for j in input.iter() {
-            if j == &x {
-                //println!("{} * {} = {}", i, j, i*j );
-                return i*j;
+    if k == 0 || k > input.len() {
+        return None;
+    }
+
+    // Indices sorted by value, so that the enumeration can stop early
+    let mut order: Vec<usize> = (0..input.len()).collect();
+    order.sort_by_key(|&i| input[i]);
+    let sorted: Vec<u64> = order.iter().map(|&i| u64::from(input[i])).collect();
+    let prefix: Vec<u64> = std::iter::once(0)
+        .chain(sorted.iter().scan(0, |sum, value| { *sum += value; Some(*sum) }))
+        .collect();
+    let target = u64::from(target);
+    let len = sorted.len();
+    if prefix[k] > target || prefix[len] - prefix[len - k] < target {
+        return None;
+    }
+
+    // Last position of each value in the sorted list
+    let mut last: HashMap<u64, usize> = HashMap::new();
+    for (pos, value) in sorted.iter().enumerate() {
+        last.insert(*value, pos);
+    }
+
+    let pairs = if k >= 4 {
+        let mut pairs = HashMap::new();
+        for i in 0..sorted.len() {
+            for j in i + 1..sorted.len() {
+                if sorted[i] + sorted[j] > target {
+                    break;
+                }
+                pairs.insert(sorted[i] + sorted[j], (i, j));
             }
         }
+        Some(pairs)
+    } else {
+        None
+    };
+
+    let search = KSum{sorted, prefix, last, pairs};
+    let mut picked = Vec::with_capacity(k);
+    if search.from(0, k, target, &mut picked) {
+        Some(picked.iter().map(|&pos| order[pos]).collect())
+    } else {
+        None
+    }
+}
+
+impl KSum {
+    /*
+     * @brief Recursive helper of k_sum_indices, working on sorted values
+     *
+     * @param start:    first position that can still be picked
+     * @param k:        number of values left to pick
+     * @param target:   remaining sum
+     * @param picked:   positions picked so far, completed on success
+     *
+     * @return true if the k values were found
+     */
+    fn from(&self, start: usize, k: usize, target: u64, picked: &mut Vec<usize>) -> bool
+    {
+        let len = self.sorted.len();
+        if k == 1 {
+            // The value may appear several times, any position after start is fine
+            return match self.last.get(&target) {
+                Some(&pos) if pos >= start => { picked.push(pos); true },
+                _ => false,
+            };
+        }
+        if let (2, Some(pairs)) = (k, &self.pairs) {
+            // Pairs are inserted by increasing i: the one kept has the largest i
+            return match pairs.get(&target) {
+                Some(&(i, j)) if i >= start => { picked.extend([i, j]); true },
+                _ => false,
+            };
+        }
+
+        for pos in start..=len - k {
+            // The smallest sum from here only grows with pos
+            if self.prefix[pos + k] - self.prefix[pos] > target {
+                break;
+            }
+            // The largest sum with this value, the others being the largest ones
+            if self.sorted[pos] + self.prefix[len] - self.prefix[len - (k - 1)] < target {
+                continue;
+            }
+            // An equal value was already tried with more choices left
+            if pos > start && self.sorted[pos] == self.sorted[pos - 1] {
+                continue;
+            }
+            picked.push(pos);
+            if self.from(pos + 1, k - 1, target - self.sorted[pos], picked) {
+                return true;
+            }
+            picked.pop();
+        }
+        false
     }
-    0
 }
 
 /*
- * @brief Find three entries that sum to the target. Relies on solve_2_values
+ * @brief Find k distinct entries that sum to the target
  *
  * @param input:    array of values
- * @param target:   target sum of 3 values of input
+ * @param k:        number of entries to combine
+ * @param target:   target sum of k values of input
  *
- * @return product of the three values, or 0 if not found
+ * @return the matching entries, in input order, or None if not found
  */
-pub fn solve_3_values(input: &[u32], target: u32) -> u32
+pub fn k_sum(input: &[u32], k: usize, target: u32) -> Option<Vec<u32>>
 {
-    for i in input.iter() {
-        let x = target - i;
+    let mut indices = k_sum_indices(input, k, target)?;
+    indices.sort_unstable();
+    Some(indices.iter().map(|&i| input[i]).collect())
+}
 
-        let res = solve_2_values(input, x);
-        if res != 0 {
-            return i * res;
-        }
-    }
-    0
+/*
+ * @brief Product of the k entries that sum to the target
+ */
+fn k_product(input: &[u32], k: usize, target: u32) -> Option<u64>
+{
+    k_sum(input, k, target).map(|entries| entries.iter().map(|&e| u64::from(e)).product())
 }
 
 // ---------------------------------------------------------------------------
 // Entry points
-#[aoc(day1, part1, naive)]
-pub fn part1(input: &[u32]) -> u32
+#[aoc(day1, part1)]
+pub fn part1(input: &[u32]) -> Option<u64>
 {
-    solve_2_values(input, 2020)
+    k_product(input, 2, 2020)
 }
 
-#[aoc(day1, part2, naive)]
-pub fn part2(input: &[u32]) -> u32
+#[aoc(day1, part2)]
+pub fn part2(input: &[u32]) -> Option<u64>
 {
-    solve_3_values(input, 2020)
+    k_product(input, 3, 2020)
 }
 
 // ---------------------------------------------------------------------------
 #[cfg(test)]
 mod tests {
-    use super::{part1};
-    use super::{part2};
+    use super::*;
+
+    static SAMPLE: [u32; 6] = [1721, 979, 366, 299, 675, 1456];
 
     #[test]
     fn sample1() {
-        let input = [1721, 979, 366, 299, 675, 1456].to_vec();
-        let product = part1(&input);
-        assert_eq!(product, 514579);
+        let input = SAMPLE.to_vec();
+        assert_eq!(k_sum(&input, 2, 2020), Some(vec![1721, 299]));
+        assert_eq!(k_sum_indices(&input, 2, 2020).map(|mut v| { v.sort(); v }), Some(vec![0, 3]));
+        assert_eq!(part1(&input), Some(514579));
     }
     #[test]
     fn sample2() {
-        let input = [1721, 979, 366, 299, 675, 1456].to_vec();
-        let res = part2(&input);
-        assert_eq!(res, 241861950);
+        let input = SAMPLE.to_vec();
+        assert_eq!(k_sum(&input, 3, 2020), Some(vec![979, 366, 675]));
+        assert_eq!(part2(&input), Some(241861950));
+    }
+
+    #[test]
+    fn check_not_found() {
+        assert_eq!(k_sum(&SAMPLE, 2, 1), None);
+        assert_eq!(k_sum(&SAMPLE, 0, 0), None);
+        assert_eq!(k_sum(&SAMPLE, 7, 2020), None);
+        assert_eq!(k_sum(&[], 1, 0), None);
+    }
+
+    #[test]
+    fn check_zero_and_duplicates() {
+        // A product of 0 is a real answer, not a failure
+        assert_eq!(k_sum(&[0, 5, 2020], 2, 2020), Some(vec![0, 2020]));
+        assert_eq!(part1(&[0, 5, 2020]), Some(0));
+        // An entry cannot be used twice, but equal entries can
+        assert_eq!(k_sum(&[1010, 3], 2, 2020), None);
+        assert_eq!(k_sum(&[1010, 3, 1010], 2, 2020), Some(vec![1010, 1010]));
+    }
+
+    #[test]
+    fn check_input_gen() {
+        assert_eq!(input_gen("1721\n979").unwrap(), vec![1721, 979]);
+        assert_eq!(format!("{}", input_gen("1721\n97x9").unwrap_err()), "day1 line 2: invalid number `97x9`");
+    }
+
+    #[test]
+    fn check_k5() {
+        let input: Vec<u32> = (1..=1000).map(|x| x * 7).collect();
+        let entries = k_sum(&input, 5, 7 * (1 + 2 + 3 + 4 + 990)).unwrap();
+        assert_eq!(entries.len(), 5);
+        assert_eq!(entries.iter().sum::<u32>(), 7 * 1000);
+    }
+
+    #[test]
+    fn check_large_not_found() {
+        // Even entries cannot make an odd sum: every branch has to be cut or tried
+        let mut state = 7u32;
+        let mut noise = || { state = state.wrapping_mul(1_103_515_245).wrapping_add(12345); state >> 8 };
+        let input: Vec<u32> = (0..1000).map(|_| noise() % 500_000 * 2).collect();
+        assert_eq!(k_sum(&input, 3, 1_500_001), None);
+        assert_eq!(k_sum(&input, 4, 2_000_001), None);
+        assert_eq!(k_sum(&input[..200], 5, 2_500_001), None);
+        // Out of reach of the smallest or the largest values
+        assert_eq!(k_sum(&input, 5, 7), None);
+        assert_eq!(k_sum(&input, 5, 5_000_000), None);
+
+        let target = input[10] + input[250] + input[500] + input[750];
+        let entries = k_sum(&input, 4, target).unwrap();
+        assert_eq!(entries.iter().sum::<u32>(), target);
+        let target = target + input[999];
+        assert_eq!(k_sum(&input, 5, target).unwrap().iter().sum::<u32>(), target);
     }
 }
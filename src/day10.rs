@@ -1,11 +1,12 @@
 /**
  * Day 10 - Adapter Array
  */
-use std::fmt;
-use parse_display::{Display, FromStr};
-use petgraph::dot::{Dot, Config};
+use petgraph::dot::Dot;
 use petgraph::graphmap::DiGraphMap;
-use petgraph::algo::all_simple_paths;
+use rand::Rng;
+use crate::error::{self, ParseError};
+
+const DAY: u32 = 10;
 
 // ---------------------------------------------------------------------------
 // Data types
@@ -16,11 +17,11 @@ use petgraph::algo::all_simple_paths;
 // ---------------------------------------------------------------------------
 // Implementations
 // ---------------------------------------------------------------------------
-fn build_graph(input: &str) -> DiGraphMap<u32, u32> {
+fn build_graph(adapters: &[u32]) -> DiGraphMap<u32, u32> {
     let mut graph = DiGraphMap::<u32, u32>::new();
-    let mut list = input_gen(input);
+    let mut list = adapters.to_vec();
     list.push(0);
-    list.sort();
+    list.sort_unstable();
 
     loop {
         let item = list[0];
@@ -33,17 +34,113 @@ fn build_graph(input: &str) -> DiGraphMap<u32, u32> {
             if diff > 3 { 
                 break; 
             } else {
-                println!("edge {} -> {} ({})", item, value, diff);
+                trace!(Trace, "edge {} -> {} ({})", item, value, diff);
                 graph.add_edge(item, *value, diff);
             }
         }
         list = remainder;
     }
 
-    println!("{:?}", Dot::new(&graph));
+    trace!(Debug, "{:?}", Dot::new(&graph));
     graph
 }
 
+/** Outlet, adapters in increasing order, then the device */
+fn chain(adapters: &[u32]) -> Vec<u32> {
+    let mut list = adapters.to_vec();
+    list.push(0);
+    list.sort_unstable();
+    let device = list[list.len() - 1] + 3;
+    list.push(device);
+    list
+}
+
+/**
+ * For each element of the chain, number of ways to reach the device from it.
+ * Adapters are sorted, so only the next three can be plugged into one.
+ * Return None if a count does not fit in an u64.
+ */
+fn ways_to_device(chain: &[u32]) -> Option<Vec<u64>> {
+    let mut ways = vec![0u64; chain.len()];
+    let last = chain.len() - 1;
+    ways[last] = 1;
+    for i in (0..last).rev() {
+        let mut total: u64 = 0;
+        for j in (i + 1..chain.len()).take_while(|j| chain[*j] - chain[i] <= 3) {
+            total = total.checked_add(ways[j])?;
+        }
+        ways[i] = total;
+    }
+    Some(ways)
+}
+
+/** Number of distinct adapter arrangements, in linear time */
+fn count_arrangements(adapters: &[u32]) -> Option<u64> {
+    ways_to_device(&chain(adapters)).map(|ways| ways[0])
+}
+
+/**
+ * Every arrangement (adapters used, in order), at most `limit` of them.
+ * There can be exponentially many: only use it on small inputs.
+ */
+pub fn arrangements(adapters: &[u32], limit: usize) -> Vec<Vec<u32>> {
+    let graph = build_graph(adapters);
+    let last = adapters.iter().copied().max().unwrap_or(0);
+    let mut found = Vec::new();
+    let mut path = Vec::new();
+    let mut todo = vec![(0, 0)];
+    while let Some((node, depth)) = todo.pop() {
+        if found.len() >= limit {
+            break;
+        }
+        path.truncate(depth);
+        if node != 0 {
+            path.push(node);
+        }
+        if node == last {
+            found.push(path.clone());
+            continue;
+        }
+        // Reversed so that arrangements come out in lexicographic order
+        let mut next: Vec<u32> = graph.neighbors(node).collect();
+        next.sort_unstable_by(|a, b| b.cmp(a));
+        todo.extend(next.into_iter().map(|n| (n, path.len())));
+    }
+    found
+}
+
+/**
+ * An arrangement chosen uniformly at random: at each step, the next adapter
+ * is picked with a weight equal to the number of ways to finish from it.
+ */
+pub fn sample_arrangement<R: Rng>(adapters: &[u32], rng: &mut R) -> Option<Vec<u32>> {
+    let chain = chain(adapters);
+    let ways = ways_to_device(&chain)?;
+    if ways[0] == 0 {
+        return None;
+    }
+    let last = chain.len() - 1;
+    let mut arrangement = Vec::new();
+    let mut i = 0;
+    while i + 1 < last {
+        let mut pick = rng.gen_range(0..ways[i]);
+        let next = (i + 1..chain.len())
+            .find(|j| {
+                if pick < ways[*j] {
+                    true
+                } else {
+                    pick -= ways[*j];
+                    false
+                }
+            })?;
+        if next < last {
+            arrangement.push(chain[next]);
+        }
+        i = next;
+    }
+    Some(arrangement)
+}
+
 fn check_one_three(diff: u32, ones: &mut u32, threes: &mut u32) {
     match diff {
         1 => *ones += 1,
@@ -55,19 +152,20 @@ fn check_one_three(diff: u32, ones: &mut u32, threes: &mut u32) {
 // ---------------------------------------------------------------------------
 // Input builder
 // ---------------------------------------------------------------------------
-fn input_gen(input: &str) -> Vec<u32> {
-    input.lines().map(|l| l.parse().unwrap()).collect()
+#[aoc_generator(day10)]
+fn input_gen(input: &str) -> Result<Vec<u32>, ParseError> {
+    error::parse_lines(input, |l| error::parse_number(DAY, l))
 }
 
 // ---------------------------------------------------------------------------
 // Solvers
 // ---------------------------------------------------------------------------
 #[aoc(day10, part1)]
-fn part1(input: &str) -> u32 {
-    let mut list = input_gen(input);
+fn part1(adapters: &[u32]) -> u32 {
+    let mut list = adapters.to_vec();
     let mut ones = 0;
     let mut threes = 1;
-    list.sort();
+    list.sort_unstable();
 
     // Charging output is 0
     let mut current: u32 = 0;
@@ -80,6 +178,11 @@ fn part1(input: &str) -> u32 {
     ones * threes
 }
 
+#[aoc(day10, part2)]
+fn part2(adapters: &[u32]) -> Option<u64> {
+    count_arrangements(adapters)
+}
+
 // ---------------------------------------------------------------------------
 
 // ---------------------------------------------------------------------------
@@ -87,7 +190,7 @@ fn part1(input: &str) -> u32 {
 mod tests {
     use super::*;
 
-    static INPUT: &'static str = "16
+    static INPUT: &str = "16
 10
 15
 5
@@ -99,7 +202,7 @@ mod tests {
 12
 4";
 
-    static INPUT2: &'static str = "28
+    static INPUT2: &str = "28
 33
 18
 42
@@ -132,16 +235,55 @@ mod tests {
 3";
     #[test]
     fn test_sample() {
-        assert_eq!(35, part1(INPUT));
-        assert_eq!(220, part1(INPUT2));
+        assert_eq!(35, part1(&input_gen(INPUT).unwrap()));
+        assert_eq!(220, part1(&input_gen(INPUT2).unwrap()));
     }
 
     #[test]
-    fn test_graph() {
-        let map = build_graph(INPUT);
-        let graph = map.into_graph::<u32>();
+    fn test_arrangements() {
+        assert_eq!(Some(8), part2(&input_gen(INPUT).unwrap()));
+        assert_eq!(Some(19208), part2(&input_gen(INPUT2).unwrap()));
+        assert_eq!(Some(1), part2(&[3]));
+        // Gap too large to plug anything
+        assert_eq!(Some(0), part2(&[1, 5]));
+        // Ones only: tribonacci numbers overflow u64 eventually
+        let ones: Vec<u32> = (1..=60).collect();
+        assert_eq!(Some(4_680_045_560_037_375), part2(&ones));
+        let ones: Vec<u32> = (1..=100).collect();
+        assert_eq!(None, part2(&ones));
+    }
 
-        //all_simple_paths(map, 0, 19, 1, None);
+    #[test]
+    fn test_enumerate() {
+        let adapters = input_gen(INPUT).unwrap();
+        let all = arrangements(&adapters, usize::MAX);
+        assert_eq!(8, all.len());
+        assert_eq!(vec![1, 4, 5, 6, 7, 10, 11, 12, 15, 16, 19], all[0]);
+        assert_eq!(vec![1, 4, 7, 10, 12, 15, 16, 19], all[7]);
+        assert_eq!(3, arrangements(&adapters, 3).len());
+
+        let all = arrangements(&input_gen(INPUT2).unwrap(), usize::MAX);
+        assert_eq!(19208, all.len());
+        assert!(all.windows(2).all(|w| w[0] < w[1]));
+    }
+
+    #[test]
+    fn test_sampling() {
+        use rand::SeedableRng;
+        use rand::rngs::StdRng;
+
+        let adapters = input_gen(INPUT).unwrap();
+        let all = arrangements(&adapters, usize::MAX);
+        let mut rng = StdRng::seed_from_u64(10);
+        let mut seen = vec![0; all.len()];
+        for _ in 0..800 {
+            let arrangement = sample_arrangement(&adapters, &mut rng).unwrap();
+            let index = all.iter().position(|a| *a == arrangement).unwrap();
+            seen[index] += 1;
+        }
+        // Roughly uniform
+        assert!(seen.iter().all(|n| *n > 50), "{:?}", seen);
+        assert_eq!(None, sample_arrangement(&[1, 5], &mut rng));
     }
 
 }
@@ -1,13 +1,37 @@
 /**
  * Day 11 - Seating System
  */
+use std::collections::HashMap;
 use std::fmt;
-use parse_display::{Display, FromStr};
+use std::fmt::Write as _;
+use std::io::{self, Write};
+use std::str::FromStr;
+use std::thread;
+use std::time::Duration;
+use parse_display::Display;
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+use crate::error::ParseError;
+use crate::gif;
+use crate::grid::{Cell, Grid, DIRECTIONS8};
+
+const DAY: u32 = 11;
+
+/** Leave a seat with 4 occupied seats around */
+pub const ADJACENT: Rules = Rules{neighbourhood: Neighbourhood::Adjacent, birth: 0, death: 4};
+/** Leave a seat when seeing 5 occupied seats */
+pub const VISIBLE: Rules = Rules{neighbourhood: Neighbourhood::Visible, birth: 0, death: 5};
+
+/** Give up on layouts still changing after this many generations */
+pub const MAX_GENERATIONS: usize = 10_000;
+
+/** GIF colours of the floor, empty and occupied seats */
+const PALETTE: [[u8; 3]; 3] = [[0x30, 0x30, 0x30], [0x3c, 0xb0, 0x4a], [0xe0, 0x3c, 0x3c]];
 
 // ---------------------------------------------------------------------------
 // Data types
 // ---------------------------------------------------------------------------
-#[derive(Clone, Debug, Display, FromStr, PartialEq)]
+#[derive(Clone, Copy, Debug, Display, PartialEq, Eq)]
 enum Position {
     #[display(".")]
     Floor,
@@ -17,244 +41,461 @@ enum Position {
     Occupied,
 }
 
+/** Which seats count as neighbours of a seat */
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Neighbourhood {
+    /** The 8 surrounding cells */
+    Adjacent,
+    /** The first seat seen in each of the 8 directions */
+    Visible,
+    /** Every cell at most this many rows and columns away */
+    Radius(usize),
+}
+
+/** Rules of the seating automaton */
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Rules {
+    pub neighbourhood: Neighbourhood,
+    /** An empty seat is taken with at most this many occupied neighbours */
+    pub birth: usize,
+    /** An occupied seat is left with at least this many occupied neighbours */
+    pub death: usize,
+}
+
+/** How a simulation ended */
+#[derive(Clone, Copy, Debug, Display, PartialEq, Eq)]
+pub enum Outcome {
+    /** Nothing changes after this generation */
+    #[display("stable from generation {generation}")]
+    Stable { generation: usize },
+    /** Generation start comes back every period generations */
+    #[display("cycle of {period} generations from generation {start}")]
+    Cycle { start: usize, period: usize },
+    /** Still changing at this generation, the cap */
+    #[display("still changing at generation {generation}")]
+    Unsettled { generation: usize },
+}
+
 #[derive(Clone, Debug, PartialEq)]
 struct Seats {
-    layout: Vec<Vec<Position>>,
+    layout: Grid<Position>,
 }
 
-#[derive(Clone, Debug, PartialEq)]
-enum Direction {
-    Up,
-    Down,
-    Left,
-    Right,
-    UpLeft,
-    UpRight,
-    DownLeft,
-    DownRight,
+/**
+ * Generation by generation evolution of a layout.
+ * The neighbour seats of every seat are found once. Two buffers hold the
+ * current and the next generations, and only the seats next to a change are
+ * evaluated again: the others cannot change.
+ */
+struct Simulation {
+    rules: Rules,
+    rows: usize,
+    cols: usize,
+    /** Indexes of the neighbour seats of each cell, none for the floor */
+    neighbours: Vec<Vec<u32>>,
+    current: Vec<Position>,
+    next: Vec<Position>,
+    /** Cells to evaluate for the next generation, flagged in `queued` */
+    dirty: Vec<u32>,
+    queued: Vec<bool>,
+    occupied: u32,
+    generation: usize,
+    /**
+     * Two independent Zobrist hashes of the occupied seats, xor of their
+     * keys: the first one finds the earlier generations, the second one
+     * tells a collision from a cycle
+     */
+    keys: Vec<[u64; 2]>,
+    hash: [u64; 2],
+}
+
+/** Every generation of a layout, to show how the seats fill up */
+pub struct Animation {
+    frames: Vec<Seats>,
+    outcome: Outcome,
 }
 
 // ---------------------------------------------------------------------------
 // Implementations
 // ---------------------------------------------------------------------------
-impl Seats {
-    fn new() -> Self {
-        let layout = Vec::new();
-        Seats {layout}
+impl Position {
+    /** Index in PALETTE */
+    fn colour(self) -> u8 {
+        match self {
+            Position::Floor => 0,
+            Position::Empty => 1,
+            Position::Occupied => 2,
+        }
     }
 
-    fn from_str(input: &str) -> Self {
-        let mut seats = Seats::new();
-        for line in input.lines() {
-            seats.add_row(line);
+    /** Terminal escape sequence for the foreground colour */
+    fn ansi(self) -> &'static str {
+        match self {
+            Position::Floor => "\x1b[90m",
+            Position::Empty => "\x1b[32m",
+            Position::Occupied => "\x1b[1;31m",
         }
-
-        seats
     }
+}
 
-    fn add_row(&mut self, line: &str) {
-        let mut row = Vec::new();
-        for position in line.chars() {
-            let pos = match position {
-                '.' => Position::Floor,
-                'L' => Position::Empty,
-                '#' => Position::Occupied,
-                _ => unreachable!(),
-            };
-            row.push(pos);
+impl Cell for Position {
+    const DAY: u32 = DAY;
+    fn from_char(c: char) -> Option<Self> {
+        match c {
+            '.' => Some(Position::Floor),
+            'L' => Some(Position::Empty),
+            '#' => Some(Position::Occupied),
+            _ => None,
         }
-        self.layout.push(row);
     }
+}
 
-    fn size(&self) -> (i32, i32) {
-        (self.layout.len() as i32, self.layout[0].len() as i32)
+impl Seats {
+    fn all_occupied(&self) -> u32 {
+        self.layout.iter().filter(|p| **p == Position::Occupied).count() as u32
     }
 
-    fn get(&self, x: i32, y: i32) -> &Position {
-        if x < 0 || x >= self.size().0 ||
-            y < 0 || y >= self.size().1 {
-                return &Position::Floor;
-            }
-        &self.layout[x as usize][y as usize]
+    /** Return (rows, columns), (0, 0) for an empty layout */
+    fn size(&self) -> (usize, usize) {
+        self.layout.size()
     }
 
-    fn all_occupied(&self) -> u32 {
-        let mut cnt = 0;
-        for i in 0..self.size().0 {
-            for j in 0..self.size().1 {
-                if let Position::Occupied = self.get(i,j) {
-                    cnt += 1;
-                }
-            }
+    /** Position at pos, None outside of the layout */
+    fn get(&self, pos: (usize, usize)) -> Option<Position> {
+        self.layout.get(pos.0, pos.1).copied()
+    }
+
+    /** First seat seen from pos in the given direction, None up to the border */
+    fn first_seat(&self, pos: (usize, usize), dir: (isize, isize)) -> Option<(usize, usize)> {
+        self.layout.ray(pos, dir).find(|p| self.get(*p) != Some(Position::Floor))
+    }
+
+    /** Positions of the neighbours of pos */
+    fn neighbours(&self, pos: (usize, usize), neighbourhood: Neighbourhood) -> Vec<(usize, usize)> {
+        match neighbourhood {
+            Neighbourhood::Adjacent => self.layout.neighbours8(pos).collect(),
+            Neighbourhood::Visible => DIRECTIONS8.iter().filter_map(|dir| self.first_seat(pos, *dir)).collect(),
+            Neighbourhood::Radius(radius) => {
+                let r = radius as isize;
+                let offsets: Vec<(isize, isize)> = (-r..=r)
+                    .flat_map(|dr| (-r..=r).map(move |dc| (dr, dc)))
+                    .filter(|offset| *offset != (0, 0))
+                    .collect();
+                self.layout.neighbours(pos, &offsets).collect()
+            },
         }
-        cnt
-    }
-    
-    /** True if one seat is occupied in the given direction */
-    fn direction_occupied(&self, x:i32, y:i32, dir: &Direction) -> bool {
-        let mut i = x;
-        let mut j = y;
-        loop {
-            match dir {
-                Direction::Up => i -= 1,
-                Direction::Down => i += 1,
-                Direction::Left => j -= 1,
-                Direction::Right =>j += 1,
-                Direction::UpLeft => {i -=1; j-=1;},
-                Direction::UpRight => {i -= 1; j += 1;},
-                Direction::DownLeft => {i += 1; j -= 1;},
-                Direction::DownRight => {i += 1; j += 1;},
-            }
-            if i < 0 || i > self.size().0 ||
-                j < 0 || j > self.size().1 {
-                    return false;
-                }
-            if let Position::Occupied = self.get(i,j) {
-                return true;
+    }
+
+    /** Count occupied neighbours of pos */
+    fn count_occupied(&self, pos: (usize, usize), neighbourhood: Neighbourhood) -> usize {
+        self.neighbours(pos, neighbourhood).into_iter()
+            .filter(|p| self.get(*p) == Some(Position::Occupied))
+            .count()
+    }
+
+    /** Map of the number of occupied seats around each seat */
+    fn occupied_map(&self) -> String {
+        let mut map = String::new();
+        for (pos, seat) in self.layout.enumerate() {
+            match seat {
+                Position::Floor => map.push('.'),
+                _ => map.push_str(&self.count_occupied(pos, Neighbourhood::Adjacent).to_string()),
             }
-            if let Position::Empty = self.get(i,j) {
-                return false;
+            if pos.1 + 1 == self.size().1 {
+                map.push('\n');
             }
         }
+        map
     }
 
-    /** Count occupied seats around x,y in all directions */
-    fn count_visible_occupied(&self, x:i32, y:i32) -> i32 {
-        let mut cnt: i32 = 0;
-        for dir in &[Direction::Up, Direction::Down, 
-                     Direction::Left, Direction::Right,
-                     Direction::UpLeft, Direction::UpRight,
-                     Direction::DownLeft, Direction::DownRight] {
-            if let true = self.direction_occupied(x, y, dir) {
-                cnt += 1;
+    /** Run for at most limit generations, return the outcome and the number of occupied seats */
+    fn run(&self, rules: &Rules, limit: usize) -> (Outcome, u32) {
+        let mut simulation = Simulation::new(self, *rules);
+        let mut seen = HashMap::new();
+        trace!(Debug, "\n{}", self);
+        let outcome = loop {
+            if let Some(outcome) = simulation.advance(&mut seen, limit) {
+                break outcome;
             }
-        }
-        cnt
+            trace!(Debug, "\n{}", simulation.seats());
+            trace!(Trace, "\n{}", simulation.seats().occupied_map());
+        };
+        trace!(Info, "{:?}", outcome);
+        (outcome, simulation.occupied())
     }
 
-    /** Count occupied seats around x,y */
-    fn count_adjacent_occupied(&self, x:i32, y:i32) -> i32 {
-        let mut cnt: i32 = 0;
-        for i in x-1..=x+1 {
-            for j in y-1..=y+1 {
-                if i == x && j == y { continue; }
-                if let Position::Occupied = self.get(i, j) {
-                    cnt += 1;
-                }
-            }
+    /** Number of occupied seats once nothing changes, None if that never happens */
+    fn settled(&self, rules: &Rules) -> Option<u32> {
+        match self.run(rules, MAX_GENERATIONS) {
+            (Outcome::Stable{..}, occupied) => Some(occupied),
+            _ => None,
         }
-        cnt
     }
+}
 
-    fn evolution_visible(&self, x: i32, y: i32, tolerance: i32) -> Position {
-        match self.get(x, y) {
+impl Rules {
+    /** Next state of a position with some occupied neighbours */
+    fn next(&self, position: Position, occupied: usize) -> Position {
+        match position {
             Position::Floor => Position::Floor,
-            Position::Empty => match self.count_visible_occupied(x,y) {
-                0 => Position::Occupied,
-                _ => Position::Empty,
+            Position::Empty => {
+                if occupied <= self.birth { Position::Occupied }
+                else { Position::Empty }
             },
-            Position::Occupied => { 
-                if self.count_visible_occupied(x,y) > tolerance { Position::Empty } 
-                else { Position::Occupied } 
+            Position::Occupied => {
+                if occupied >= self.death { Position::Empty }
+                else { Position::Occupied }
             },
         }
     }
+}
 
-    /** Count occupied seats around x,y and compute evolution */
-    fn evolution(&self, x: i32, y: i32, tolerance: i32) -> Position {
-        match self.get(x, y) {
-            Position::Floor => Position::Floor,
-            Position::Empty => match self.count_adjacent_occupied(x,y) {
-                0 => Position::Occupied,
-                _ => Position::Empty,
-            },
-            Position::Occupied => { 
-                if self.count_adjacent_occupied(x,y) > tolerance { Position::Empty } 
-                else { Position::Occupied } 
-            },
+impl Simulation {
+    fn new(seats: &Seats, rules: Rules) -> Self {
+        let layout = &seats.layout;
+        let cols = layout.cols();
+        let neighbours: Vec<Vec<u32>> = layout.enumerate()
+            .map(|(pos, position)| match position {
+                Position::Floor => Vec::new(),
+                _ => seats.neighbours(pos, rules.neighbourhood).into_iter()
+                    .filter(|n| seats.get(*n) != Some(Position::Floor))
+                    .map(|(row, col)| (row * cols + col) as u32)
+                    .collect(),
+            })
+            .collect();
+        let current = layout.cells().to_vec();
+        let dirty: Vec<u32> = (0..current.len() as u32)
+            .filter(|i| current[*i as usize] != Position::Floor)
+            .collect();
+        let mut queued = vec![false; current.len()];
+        for i in dirty.iter() {
+            queued[*i as usize] = true;
+        }
+        let occupied = seats.all_occupied();
+        let mut rng = StdRng::seed_from_u64(u64::from(DAY));
+        let keys: Vec<[u64; 2]> = current.iter().map(|_| rng.gen()).collect();
+        let hash = current.iter().zip(keys.iter())
+            .filter(|(position, _)| **position == Position::Occupied)
+            .fold([0, 0], |hash, (_, key)| [hash[0] ^ key[0], hash[1] ^ key[1]]);
+        Simulation{
+            rules, rows: layout.rows(), cols, neighbours,
+            next: current.clone(), current, dirty, queued, occupied, generation: 0,
+            keys, hash,
+        }
+    }
+
+    /**
+     * Compute the next generation, unless the generation cap is reached.
+     * Return how the simulation ended, if it did. seen holds the generations
+     * so far with their second hash, by first hash: both must match to
+     * declare a cycle, so that a collision is not taken for one.
+     */
+    fn advance(&mut self, seen: &mut HashMap<u64, Vec<(usize, u64)>>, limit: usize) -> Option<Outcome> {
+        seen.entry(self.hash[0]).or_default().push((self.generation, self.hash[1]));
+        if self.generation >= limit {
+            return Some(Outcome::Unsettled{generation: self.generation});
         }
+        if !self.step() {
+            // The step that changed nothing still counts as a generation
+            return Some(Outcome::Stable{generation: self.generation - 1});
+        }
+        seen.get(&self.hash[0])?.iter()
+            .find(|(_, check)| *check == self.hash[1])
+            .map(|(start, _)| Outcome::Cycle{start: *start, period: self.generation - start})
     }
 
-    fn print_occupied(&self) {
-        for i in 0..self.size().0 {
-            for j in 0..self.size().1 {
-                if let Position::Floor = self.get(i,j) {
-                    print!(".");
-                } else {
-                    print!("{}", self.count_adjacent_occupied(i,j));
+    /** Compute the next generation. Return true if a seat changed */
+    fn step(&mut self) -> bool {
+        let mut dirty = std::mem::take(&mut self.dirty);
+        let mut changed = Vec::new();
+        for index in dirty.drain(..) {
+            let i = index as usize;
+            self.queued[i] = false;
+            let current = &self.current;
+            let occupied = self.neighbours[i].iter()
+                .filter(|n| current[**n as usize] == Position::Occupied)
+                .count();
+            let next = self.rules.next(current[i], occupied);
+            if next != current[i] {
+                self.next[i] = next;
+                changed.push(index);
+            }
+        }
+        std::mem::swap(&mut self.current, &mut self.next);
+
+        // Bring the other buffer up to date, and queue the changes and their
+        // neighbours (the neighbour relation is symmetric)
+        for index in changed.iter() {
+            let i = *index as usize;
+            self.next[i] = self.current[i];
+            self.hash[0] ^= self.keys[i][0];
+            self.hash[1] ^= self.keys[i][1];
+            if self.current[i] == Position::Occupied {
+                self.occupied += 1;
+            } else {
+                self.occupied -= 1;
+            }
+            for n in std::iter::once(index).chain(self.neighbours[i].iter()) {
+                if !self.queued[*n as usize] {
+                    self.queued[*n as usize] = true;
+                    dirty.push(*n);
                 }
             }
-            println!("");
         }
-        println!("");
+        self.dirty = dirty;
+        self.generation += 1;
+        trace!(Trace, "Generation {}: {} changes", self.generation, changed.len());
+        !changed.is_empty()
+    }
+
+    fn occupied(&self) -> u32 {
+        self.occupied
     }
 
-    fn run_once_visible(&mut self) -> bool {
-        let orig = self.clone();
+    /** Layout of the current generation */
+    fn seats(&self) -> Seats {
+        let layout = Grid::from_cells(self.rows, self.cols, self.current.clone());
+        Seats{layout: layout.expect("the simulation keeps the layout size")}
+    }
+}
 
-        for x in 0..orig.size().0 {
-            for y in 0..orig.size().1 {
-                let evo = orig.evolution_visible(x, y, 4);
-                //println!("{} => {}", self.get(x,y), evo);
-                self.layout[x as usize][y as usize] = evo;
+impl Animation {
+    /**
+     * Generations until the layout stops changing, or comes back to an
+     * earlier generation, or at most limit steps
+     */
+    pub fn new(layout: &str, rules: &Rules, limit: usize) -> Result<Self, ParseError> {
+        let seats: Seats = layout.parse()?;
+        let mut simulation = Simulation::new(&seats, *rules);
+        let mut seen = HashMap::new();
+        let mut frames = vec![seats];
+        let outcome = loop {
+            if let Some(outcome) = simulation.advance(&mut seen, limit) {
+                break outcome;
             }
-        }
+            frames.push(simulation.seats());
+        };
+        Ok(Animation{frames, outcome})
+    }
+
+    /** How the simulation ended */
+    pub fn outcome(&self) -> Outcome {
+        self.outcome
+    }
 
-        *self != orig
+    /** Number of frames, the initial layout included */
+    pub fn len(&self) -> usize {
+        self.frames.len()
     }
 
-    fn run_once_adjacent(&mut self) -> bool {
-        let orig = self.clone();
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    /** Each generation in colour, followed by a status line */
+    pub fn ansi_frames(&self) -> Vec<String> {
+        self.frames.iter().enumerate()
+            .map(|(generation, seats)| {
+                let mut frame = String::new();
+                for row in seats.layout.iter_rows() {
+                    let mut colour = "";
+                    for position in row {
+                        if position.ansi() != colour {
+                            colour = position.ansi();
+                            frame.push_str(colour);
+                        }
+                        let _ = write!(frame, "{}", position);
+                    }
+                    frame.push_str("\x1b[0m\n");
+                }
+                let _ = writeln!(frame, "Generation {}: {} occupied seats", generation, seats.all_occupied());
+                frame
+            })
+            .collect()
+    }
+
+    /** Play the animation in a terminal, redrawing each frame in place */
+    pub fn play<W: Write>(&self, mut out: W, delay: Duration) -> io::Result<()> {
+        write!(out, "\x1b[2J")?;
+        for frame in self.ansi_frames() {
+            write!(out, "\x1b[H{}", frame)?;
+            out.flush()?;
+            thread::sleep(delay);
+        }
+        Ok(())
+    }
 
-        for x in 0..orig.size().0 {
-            for y in 0..orig.size().1 {
-                let evo = orig.evolution(x, y, 3);
-                //println!("{} => {}", self.get(x,y), evo);
-                self.layout[x as usize][y as usize] = evo;
+    /**
+     * Animated GIF, each seat drawn as a square of scale pixels,
+     * each frame shown for delay 1/100 s
+     */
+    pub fn write_gif<W: Write>(&self, out: W, scale: usize, delay: u16) -> io::Result<W> {
+        let (rows, cols) = self.frames.first().map_or((0, 0), Seats::size);
+        let (width, height) = (cols * scale, rows * scale);
+        let mut encoder = gif::Encoder::new(out, width, height, &PALETTE)?;
+        for seats in self.frames.iter() {
+            let mut pixels = Vec::with_capacity(width * height);
+            for row in seats.layout.iter_rows() {
+                let line: Vec<u8> = row.iter()
+                    .flat_map(|p| std::iter::repeat_n(p.colour(), scale))
+                    .collect();
+                for _ in 0..scale {
+                    pixels.extend_from_slice(&line);
+                }
             }
+            encoder.frame(&pixels, delay)?;
         }
+        encoder.finish()
+    }
+}
 
-        *self != orig
+impl FromStr for Seats {
+    type Err = ParseError;
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        Ok(Seats{layout: input.parse()?})
     }
 }
 
 impl fmt::Display for Seats {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        for row in self.layout.iter() {
-            for seat in row.iter() {
-                write!(f, "{}", seat)?;
-            }
-            write!(f, "\n")?;
-        }
-        Ok(())
+        write!(f, "{}", self.layout)
     }
 }
 
 // ---------------------------------------------------------------------------
 // Input builder
 // ---------------------------------------------------------------------------
+#[aoc_generator(day11)]
+fn input_gen(input: &str) -> Result<Seats, ParseError> {
+    input.parse()
+}
 
 // ---------------------------------------------------------------------------
 // Solvers
 // ---------------------------------------------------------------------------
+/**
+ * Number of occupied seats once the layout stops changing under some rules,
+ * None if it cycles or is still changing after MAX_GENERATIONS
+ */
+pub fn occupied_seats(layout: &str, rules: &Rules) -> Result<Option<u32>, ParseError> {
+    let seats: Seats = layout.parse()?;
+    Ok(seats.settled(rules))
+}
+
+/** Run for at most limit generations, return the outcome and the number of occupied seats */
+pub fn simulate(layout: &str, rules: &Rules, limit: usize) -> Result<(Outcome, u32), ParseError> {
+    let seats: Seats = layout.parse()?;
+    Ok(seats.run(rules, limit))
+}
+
 #[aoc(day11, part1)]
-fn part1(input: &str) -> u32 {
-    let mut seats = Seats::from_str(input);
-    println!("{}", seats);
-    while seats.run_once_adjacent() {
-        println!("{}", seats);
-    }
-    seats.all_occupied()
+fn part1(input: &Seats) -> Option<u32> {
+    input.settled(&ADJACENT)
 }
 
 #[aoc(day11, part2)]
-fn part2(input: &str) -> u32 {
-    let mut seats = Seats::from_str(input);
-    println!("{}", seats);
-    while seats.run_once_visible() {
-        println!("{}", seats);
-    }
-    seats.all_occupied()
+fn part2(input: &Seats) -> Option<u32> {
+    input.settled(&VISIBLE)
 }
 
 // ---------------------------------------------------------------------------
@@ -263,7 +504,7 @@ fn part2(input: &str) -> u32 {
 mod tests {
     use super::*;
 
-    static INPUT: &'static str = "L.LL.LL.LL
+    static INPUT: &str = "L.LL.LL.LL
 LLLLLLL.LL
 L.L.L..L..
 LLLL.LL.LL
@@ -274,7 +515,7 @@ LLLLLLLLLL
 L.LLLLLL.L
 L.LLLLL.LL";
 
-    static ITER1: &'static str = "#.##.##.##
+    static ITER1: &str = "#.##.##.##
 #######.##
 #.#.#..#..
 ####.##.##
@@ -285,7 +526,7 @@ L.LLLLL.LL";
 #.######.#
 #.#####.##";
 
-    static ITER2: &'static str = "#.LL.L#.##
+    static ITER2: &str = "#.LL.L#.##
 #LLLLLL.L#
 L.L.L..L..
 #LLL.LL.L#
@@ -296,7 +537,7 @@ L.L.L..L..
 #.LLLLLL.L
 #.#LLLL.##";
 
-    static ITER3: &'static str = "#.##.L#.##
+    static ITER3: &str = "#.##.L#.##
 #L###LL.L#
 L.#.#..#..
 #L##.##.L#
@@ -307,7 +548,7 @@ L.#.#..#..
 #.LL###L.L
 #.#L###.##";
 
-    static ITER4: &'static str = "#.#L.L#.##
+    static ITER4: &str = "#.#L.L#.##
 #LLL#LL.L#
 L.L.L..#..
 #LLL.##.L#
@@ -318,7 +559,7 @@ L.L.L..#..
 #.LLLLLL.L
 #.#L#L#.##";
 
-    static ITER5: &'static str = "#.#L.L#.##
+    static ITER5: &str = "#.#L.L#.##
 #LLL#LL.L#
 L.#.L..#..
 #L##.##.L#
@@ -331,54 +572,219 @@ L.#.L..#..
 
     #[test]
     fn test_seats() {
-        let mut seats = Seats::new();
-        for line in INPUT.lines() {
-            seats.add_row(line);
+        let seats = Seats::from_str(INPUT).unwrap();
+        assert!(seats.occupied_map().starts_with("0.00.00.00\n"));
+
+        let mut simulation = Simulation::new(&seats, ADJACENT);
+        for (i, expected) in [ITER1, ITER2, ITER3, ITER4, ITER5].iter().enumerate() {
+            assert!(simulation.step(), "generation {}", i + 1);
+            assert_eq!(Seats::from_str(expected).unwrap(), simulation.seats());
         }
-        println!("INPUT");
-        println!("{}", seats);
-        seats.print_occupied();
-
-        assert_eq!(true, seats.run_once_adjacent());
-        println!("Iteration 1");
-        println!("{}", seats);
-        seats.print_occupied();
-        assert_eq!(Seats::from_str(ITER1), seats);
-
-        assert_eq!(true, seats.run_once_adjacent());
-        println!("Iteration 2");
-        println!("{}", seats);
-        seats.print_occupied();
-        assert_eq!(Seats::from_str(ITER2), seats);
-
-        assert_eq!(true, seats.run_once_adjacent());
-        println!("Iteration 3");
-        println!("{}", seats);
-        seats.print_occupied();
-        assert_eq!(Seats::from_str(ITER3), seats);
-
-        assert_eq!(true, seats.run_once_adjacent());
-        println!("Iteration 4");
-        println!("{}", seats);
-        seats.print_occupied();
-        assert_eq!(Seats::from_str(ITER4), seats);
-
-        assert_eq!(true, seats.run_once_adjacent());
-        println!("Iteration 5");
-        println!("{}", seats);
-        seats.print_occupied();
-        assert_eq!(Seats::from_str(ITER5), seats);
-
-        assert_eq!(false, seats.run_once_adjacent());
-        //while(seats.run_once_adjacent()) { println!("{}", seats); }
-        assert_eq!(37, seats.all_occupied());
+
+        assert!(!simulation.step());
+        assert_eq!(37, simulation.occupied());
+        assert_eq!(6, simulation.generation);
     }
 
     #[test]
     fn test_part2() {
-        let mut seats = Seats::from_str(INPUT);
-        while(seats.run_once_visible()) { println!("{}", seats); }
-        assert_eq!(26, seats.all_occupied());
+        let seats = Seats::from_str(INPUT).unwrap();
+        let mut simulation = Simulation::new(&seats, VISIBLE);
+        while simulation.step() {}
+        assert_eq!(26, simulation.occupied());
+        assert_eq!(Some(26), part2(&seats));
     }
 
+    /** Straightforward generation: every seat, from a copy of the layout */
+    fn naive_step(seats: &Seats, rules: &Rules) -> Seats {
+        let mut next = seats.clone();
+        for pos in seats.layout.positions() {
+            let occupied = seats.count_occupied(pos, rules.neighbourhood);
+            next.layout[pos] = rules.next(seats.layout[pos], occupied);
+        }
+        next
+    }
+
+    #[test]
+    fn test_simulation() {
+        use rand::{Rng, SeedableRng};
+        use rand::rngs::StdRng;
+
+        let mut rng = StdRng::seed_from_u64(11);
+        let layout: String = (0..40)
+            .map(|_| (0..50).map(|_| if rng.gen_bool(0.2) { '.' } else { 'L' }).collect::<String>() + "\n")
+            .collect();
+        let seats = Seats::from_str(&layout).unwrap();
+        let variants = [
+            ADJACENT,
+            VISIBLE,
+            Rules{neighbourhood: Neighbourhood::Radius(2), birth: 1, death: 9},
+        ];
+        for rules in variants.iter() {
+            let mut simulation = Simulation::new(&seats, *rules);
+            let mut naive = seats.clone();
+            // Some rules never settle
+            for _ in 0..100 {
+                let next = naive_step(&naive, rules);
+                let changed = simulation.step();
+                assert_eq!(next, simulation.seats(), "{:?} generation {}", rules, simulation.generation);
+                assert_eq!(next.all_occupied(), simulation.occupied());
+                assert_eq!(changed, next != naive);
+                if !changed {
+                    break;
+                }
+                naive = next;
+            }
+        }
+    }
+
+    #[test]
+    fn test_neighbourhood() {
+        let seats = Seats::from_str(".......#.
+...#.....
+.#.......
+.........
+..#L....#
+....#....
+.........
+#........
+...#.....").unwrap();
+        assert_eq!(8, seats.count_occupied((4, 3), Neighbourhood::Visible));
+        assert_eq!(2, seats.count_occupied((4, 3), Neighbourhood::Adjacent));
+        assert_eq!(2, seats.count_occupied((4, 3), Neighbourhood::Radius(1)));
+        assert_eq!(3, seats.count_occupied((4, 3), Neighbourhood::Radius(2)));
+
+        let seats = Seats::from_str(".##.##.
+#.#.#.#
+##...##
+...L...
+##...##
+#.#.#.#
+.##.##.").unwrap();
+        assert_eq!(0, seats.count_occupied((3, 3), Neighbourhood::Visible));
+        let mut around = seats.neighbours((0, 0), Neighbourhood::Adjacent);
+        around.sort_unstable();
+        assert_eq!(seats.neighbours((0, 0), Neighbourhood::Radius(1)), around);
+    }
+
+    #[test]
+    fn test_rules() {
+        let seats = Seats::from_str(INPUT).unwrap();
+        assert_eq!(Some(37), part1(&seats));
+        // Same rule, written with a radius
+        let rules = Rules{neighbourhood: Neighbourhood::Radius(1), ..ADJACENT};
+        assert_eq!(Some(37), seats.settled(&rules));
+        // More tolerant people
+        let rules = Rules{death: 6, ..VISIBLE};
+        assert_eq!(Some(40), seats.settled(&rules));
+        assert_eq!(Ok(Some(40)), occupied_seats(INPUT, &rules));
+    }
+
+    #[test]
+    fn test_outcome() {
+        let seats = Seats::from_str(INPUT).unwrap();
+        assert_eq!((Outcome::Stable{generation: 5}, 37), seats.run(&ADJACENT, MAX_GENERATIONS));
+        let iter3 = Seats::from_str(ITER3).unwrap().all_occupied();
+        assert_eq!((Outcome::Unsettled{generation: 3}, iter3), seats.run(&ADJACENT, 3));
+        assert_eq!((Outcome::Unsettled{generation: 0}, 0), seats.run(&ADJACENT, 0));
+        let stable = Seats::from_str(ITER5).unwrap();
+        assert_eq!((Outcome::Stable{generation: 0}, 37), stable.run(&ADJACENT, MAX_GENERATIONS));
+
+        // Everybody leaves, then everybody comes back
+        let blink = Rules{neighbourhood: Neighbourhood::Adjacent, birth: 0, death: 0};
+        assert_eq!((Outcome::Cycle{start: 0, period: 2}, 0), seats.run(&blink, MAX_GENERATIONS));
+        assert_eq!(None, seats.settled(&blink));
+        assert_eq!(Ok((Outcome::Cycle{start: 0, period: 2}, 0)), simulate(INPUT, &blink, 10));
+        assert_eq!(Ok(None), occupied_seats(INPUT, &blink));
+        let animation = Animation::new(INPUT, &blink, 100).unwrap();
+        assert_eq!(Outcome::Cycle{start: 0, period: 2}, animation.outcome());
+        assert_eq!(2, animation.len());
+    }
+
+    #[test]
+    fn test_hash_collision() {
+        // Every layout gets the same first hash: only the second one tells them apart
+        let run = |rules: Rules| {
+            let seats = Seats::from_str(INPUT).unwrap();
+            let mut simulation = Simulation::new(&seats, rules);
+            for key in simulation.keys.iter_mut() {
+                key[0] = 0;
+            }
+            simulation.hash[0] = 0;
+            let mut seen = HashMap::new();
+            loop {
+                if let Some(outcome) = simulation.advance(&mut seen, MAX_GENERATIONS) {
+                    return (outcome, simulation.occupied());
+                }
+            }
+        };
+        assert_eq!((Outcome::Stable{generation: 5}, 37), run(ADJACENT));
+        assert_eq!((Outcome::Stable{generation: 6}, 26), run(VISIBLE));
+        let blink = Rules{neighbourhood: Neighbourhood::Adjacent, birth: 0, death: 0};
+        assert_eq!((Outcome::Cycle{start: 0, period: 2}, 0), run(blink));
+        assert_eq!("cycle of 2 generations from generation 0", run(blink).0.to_string());
+        assert_eq!("stable from generation 5", Outcome::Stable{generation: 5}.to_string());
+        assert_eq!("still changing at generation 3", Outcome::Unsettled{generation: 3}.to_string());
+    }
+
+    #[test]
+    fn test_animation() {
+        let animation = Animation::new(INPUT, &ADJACENT, 100).unwrap();
+        assert_eq!(6, animation.len());
+        assert_eq!(3, Animation::new(INPUT, &ADJACENT, 2).unwrap().len());
+
+        let frames = animation.ansi_frames();
+        assert!(frames[0].starts_with("\x1b[32mL\x1b[90m.\x1b[32mLL\x1b[90m."));
+        assert!(frames[0].ends_with("\x1b[0m\nGeneration 0: 0 occupied seats\n"));
+        assert!(frames[1].starts_with("\x1b[1;31m#\x1b[90m.\x1b[1;31m##"));
+        assert!(frames[5].ends_with("Generation 5: 37 occupied seats\n"));
+        let mut terminal = Vec::new();
+        animation.play(&mut terminal, Duration::from_millis(0)).unwrap();
+        assert_eq!(6, String::from_utf8(terminal).unwrap().matches("\x1b[H").count());
+
+        let gif = animation.write_gif(Vec::new(), 4, 50).unwrap();
+        assert_eq!(&gif[..10], b"GIF89a\x28\x00\x28\x00");
+        assert_eq!(6, gif.windows(2).filter(|w| w == b"\x21\xf9").count());
+        assert_eq!(Some(&0x3b), gif.last());
+        assert!(Animation::new("L.L\nL", &ADJACENT, 10).is_err());
+    }
+
+    #[test]
+    fn test_boundaries() {
+        let seats = Seats::from_str("").unwrap();
+        assert_eq!((0, 0), seats.size());
+        assert_eq!(None, seats.get((0, 0)));
+        assert_eq!(Some(0), seats.settled(&ADJACENT));
+        assert_eq!(Ok(Some(0)), occupied_seats("", &VISIBLE));
+
+        let seats = Seats::from_str("L.L..L").unwrap();
+        assert_eq!((1, 6), seats.size());
+        assert_eq!(None, seats.get((1, 0)));
+        assert_eq!(None, seats.get((0, 6)));
+        assert_eq!(Some((0, 2)), seats.first_seat((0, 0), (0, 1)));
+        assert_eq!(Some((0, 5)), seats.first_seat((0, 2), (0, 1)));
+        assert_eq!(None, seats.first_seat((0, 5), (0, 1)));
+        assert_eq!(None, seats.first_seat((0, 0), (0, -1)));
+        assert_eq!(None, seats.first_seat((0, 0), (-1, -1)));
+        assert_eq!(Some(3), seats.settled(&VISIBLE));
+
+        let seats = Seats::from_str("L\nL\n.\nL").unwrap();
+        assert_eq!((4, 1), seats.size());
+        assert_eq!(vec![(1, 0)], seats.neighbours((0, 0), Neighbourhood::Visible));
+        assert_eq!(vec![(0, 0), (3, 0)], seats.neighbours((1, 0), Neighbourhood::Visible));
+        assert_eq!(None, seats.first_seat((3, 0), (1, 0)));
+        assert_eq!(Some(3), seats.settled(&ADJACENT));
+        assert_eq!(Some(3), seats.settled(&VISIBLE));
+    }
+
+    #[test]
+    fn test_parse_error() {
+        let err = input_gen("L.L\nL#x").unwrap_err();
+        assert_eq!(format!("{}", err), "day11 line 2 column 3: invalid character `x`");
+        // Rows of different lengths
+        let err = input_gen("L.L\nL#").unwrap_err();
+        assert_eq!(err.line, 2);
+        assert!(occupied_seats("L.L\nL#LL", &ADJACENT).is_err());
+        assert!(occupied_seats("L.L\n\nL#L", &ADJACENT).is_err());
+    }
 }
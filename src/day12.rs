@@ -2,12 +2,16 @@
  * Day 12 - Rain Risk
  */
 use std::fmt;
+use std::str::FromStr;
 use parse_display::{Display, FromStr};
+use crate::error::{self, ParseError, ParseErrorKind};
+
+const DAY: u32 = 12;
 
 // ---------------------------------------------------------------------------
 // Data types
 // ---------------------------------------------------------------------------
-#[derive(Debug, Display, FromStr)]
+#[derive(Debug, Display, PartialEq)]
 enum Instruction {
     #[display("N{0}")]
     North(i32),
@@ -54,6 +58,26 @@ struct BoatWaypoint {
 // ---------------------------------------------------------------------------
 // Implementations
 // ---------------------------------------------------------------------------
+/** "F10": action letter immediately followed by its value */
+impl FromStr for Instruction {
+    type Err = ParseError;
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let action = input.chars().next()
+            .ok_or_else(|| ParseError::new(DAY, ParseErrorKind::Empty))?;
+        let value = error::parse_number(DAY, &input[action.len_utf8()..]).map_err(|e| e.at_column(2));
+        match action {
+            'N' => Ok(Instruction::North(value?)),
+            'S' => Ok(Instruction::South(value?)),
+            'E' => Ok(Instruction::East(value?)),
+            'W' => Ok(Instruction::West(value?)),
+            'L' => Ok(Instruction::Left(value?)),
+            'R' => Ok(Instruction::Right(value?)),
+            'F' => Ok(Instruction::Forward(value?)),
+            _ => Err(ParseError::new(DAY, ParseErrorKind::UnknownOpcode(action.to_string())).at_column(1)),
+        }
+    }
+}
+
 impl Waypoint {
     fn turn_left(&mut self) {
         let tmp = self.east;
@@ -174,30 +198,29 @@ impl fmt::Display for Boat {
 // ---------------------------------------------------------------------------
 // Input builder
 // ---------------------------------------------------------------------------
-fn input_gen(input: &str) -> Vec<Instruction> {
-    input.lines().map(|l| l.parse().unwrap()).collect()
+#[aoc_generator(day12)]
+fn input_gen(input: &str) -> Result<Vec<Instruction>, ParseError> {
+    error::parse_lines(input, str::parse)
 }
 // ---------------------------------------------------------------------------
 // Solvers
 // ---------------------------------------------------------------------------
 #[aoc(day12, part1)]
-fn part1(input: &str) -> i32 {
-    let program = input_gen(input);
+fn part1(program: &[Instruction]) -> i32 {
     let mut boat = Boat::new();
     for inst in program.iter() {
         boat.execute(inst);
-        println!("{} -> {}", inst, boat);
+        trace!(Debug, "{} -> {}", inst, boat);
     }
     boat.distance()
 }
 
 #[aoc(day12, part2)]
-fn part2(input: &str) -> i32 {
-    let program = input_gen(input);
+fn part2(program: &[Instruction]) -> i32 {
     let mut boat = BoatWaypoint::new();
     for inst in program.iter() {
         boat.execute(inst);
-        println!("{} -> {}", inst, boat);
+        trace!(Debug, "{} -> {}", inst, boat);
     }
     boat.distance()
 }
@@ -209,7 +232,7 @@ fn part2(input: &str) -> i32 {
 mod tests {
     use super::*;
 
-    static INPUT: &'static str = "F10
+    static INPUT: &str = "F10
 N3
 F7
 R90
@@ -218,18 +241,27 @@ F11";
     #[test]
     fn test_sample() {
         let mut boat = Boat::new();
-        let inst = input_gen(INPUT);
-        println!("{:?}", inst);
-        println!("{}", boat);
+        let inst = input_gen(INPUT).unwrap();
+        assert_eq!(5, inst.len());
 
         for i in inst.iter() {
             boat.execute(i);
-            println!("{} -> {}", i, boat);
         }
+        assert_eq!((17, -8), (boat.east, boat.north));
+        assert_eq!(25, boat.distance());
     }
 
     #[test]
     fn test_sample2() {
-        part2(INPUT);
+        assert_eq!(286, part2(&input_gen(INPUT).unwrap()));
+    }
+
+    #[test]
+    fn test_parse_error() {
+        assert_eq!("R90".parse(), Ok(Instruction::Right(90)));
+        let err = input_gen("F10\nX3").unwrap_err();
+        assert_eq!(format!("{}", err), "day12 line 2 column 1: unknown opcode `X`");
+        let err = input_gen("F10\nN3.5").unwrap_err();
+        assert_eq!(format!("{}", err), "day12 line 2 column 2: invalid number `3.5`");
     }
 }
@@ -0,0 +1,145 @@
+/**
+ * Day 13 - Shuttle Search
+ */
+use crate::error::{self, ParseError, ParseErrorKind};
+use crate::math;
+
+const DAY: u32 = 13;
+
+// ---------------------------------------------------------------------------
+// Data types
+// ---------------------------------------------------------------------------
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Schedule {
+    /** Earliest timestamp to leave */
+    earliest: u64,
+    /** Bus IDs, in the order of the list; None for `x` */
+    buses: Vec<Option<u64>>,
+}
+
+// ---------------------------------------------------------------------------
+// Implementations
+// ---------------------------------------------------------------------------
+/** Bus ID or `x`. A bus ID is its period, so it cannot be 0 */
+fn parse_bus(input: &str) -> Result<Option<u64>, ParseError> {
+    match input {
+        "x" => Ok(None),
+        _ => match error::parse_number(DAY, input)? {
+            0 => Err(ParseError::new(DAY, ParseErrorKind::InvalidNumber(String::from(input)))),
+            id => Ok(Some(id)),
+        },
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Input builder
+// ---------------------------------------------------------------------------
+#[aoc_generator(day13)]
+fn input_gen(input: &str) -> Result<Schedule, ParseError> {
+    let mut lines = input.lines();
+    let earliest = lines.next()
+        .ok_or_else(|| ParseError::new(DAY, ParseErrorKind::Empty))
+        .and_then(|l| error::parse_number(DAY, l.trim()))
+        .map_err(|e| e.at_line(1))?;
+
+    let line = lines.next()
+        .ok_or_else(|| ParseError::new(DAY, ParseErrorKind::Unexpected{
+            expected: "a list of buses", found: String::from("end of input")}).at_line(2))?;
+    let mut buses = Vec::new();
+    let mut column = 1;
+    for item in line.trim_end().split(',') {
+        buses.push(parse_bus(item).map_err(|e| e.at_line(2).at_column(column))?);
+        column += item.len() + 1;
+    }
+    Ok(Schedule{earliest, buses})
+}
+
+// ---------------------------------------------------------------------------
+// Solvers
+// ---------------------------------------------------------------------------
+/** ID of the first bus leaving after the earliest timestamp, times the wait */
+#[aoc(day13, part1)]
+fn part1(schedule: &Schedule) -> Option<u64> {
+    let (wait, id) = schedule.buses.iter()
+        .flatten()
+        .map(|id| ((id - schedule.earliest % id) % id, *id))
+        .min()?;
+    trace!(Debug, "Bus {} after {} minutes", id, wait);
+    Some(wait * id)
+}
+
+/** Earliest timestamp where each bus leaves at its offset in the list */
+#[aoc(day13, part2)]
+fn part2(schedule: &Schedule) -> Option<u64> {
+    let congruences: Vec<(u64, u64)> = schedule.buses.iter().enumerate()
+        .filter_map(|(offset, id)| id.map(|id| {
+            // t + offset ≡ 0 (mod id)
+            let offset = offset as u64 % id;
+            ((id - offset) % id, id)
+        }))
+        .collect();
+    match math::crt(&congruences) {
+        Ok((t, _)) => Some(t),
+        Err(e) => {
+            trace!(Debug, "No timestamp: {}", e);
+            None
+        },
+    }
+}
+
+// ---------------------------------------------------------------------------
+// ---------------------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    static INPUT: &str = "939
+7,13,x,x,59,x,31,19";
+
+    fn buses(list: &str) -> Schedule {
+        input_gen(&format!("0\n{}", list)).unwrap()
+    }
+
+    #[test]
+    fn test_parse() {
+        let schedule = input_gen(INPUT).unwrap();
+        assert_eq!(schedule.earliest, 939);
+        assert_eq!(schedule.buses, vec![Some(7), Some(13), None, None, Some(59), None, Some(31), Some(19)]);
+
+        let err = input_gen("939\n7,13,y,5").unwrap_err();
+        assert_eq!(format!("{}", err), "day13 line 2 column 6: invalid number `y`");
+        let err = input_gen("939\n7,0").unwrap_err();
+        assert_eq!(format!("{}", err), "day13 line 2 column 3: invalid number `0`");
+        let err = input_gen("soon\n7").unwrap_err();
+        assert_eq!(err.line, 1);
+        let err = input_gen("939").unwrap_err();
+        assert_eq!(err.line, 2);
+    }
+
+    #[test]
+    fn test_part1() {
+        assert_eq!(part1(&input_gen(INPUT).unwrap()), Some(295));
+        assert_eq!(part1(&input_gen("10\n5,x").unwrap()), Some(0));
+        assert_eq!(part1(&input_gen("10\nx,x").unwrap()), None);
+    }
+
+    #[test]
+    fn test_part2() {
+        assert_eq!(part2(&input_gen(INPUT).unwrap()), Some(1068781));
+        assert_eq!(part2(&buses("17,x,13,19")), Some(3417));
+        assert_eq!(part2(&buses("67,7,59,61")), Some(754018));
+        assert_eq!(part2(&buses("67,x,7,59,61")), Some(779210));
+        assert_eq!(part2(&buses("67,7,x,59,61")), Some(1261476));
+        assert_eq!(part2(&buses("1789,37,47,1889")), Some(1202161486));
+    }
+
+    #[test]
+    fn test_part2_edge_cases() {
+        // Offsets larger than the bus ID
+        assert_eq!(part2(&buses("2,x,x,3")), Some(0));
+        // Non coprime IDs
+        assert_eq!(part2(&buses("4,x,6")), Some(4));
+        assert_eq!(part2(&buses("2,4")), None);
+        assert_eq!(part2(&buses("x,x")), Some(0));
+    }
+}
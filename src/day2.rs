@@ -1,26 +1,15 @@
 /**
  * Day 2 - Password Philosophy
  */
-//use error_chain::error_chain;
 use std::str::FromStr;
-use std::error::Error;
 use parse_display::{Display, FromStr};
+use crate::error::{self, ParseError, ParseErrorKind};
+
+const DAY: u32 = 2;
 
 // ---------------------------------------------------------------------------
 // Data types
 // ---------------------------------------------------------------------------
-/*
-mod errors {
-    error_chain!{
-        foreign_links {
-            ParseChar(::std::char::ParseCharError);
-            ParseInt(::std::num::ParseIntError);
-        }
-    }
-}
-use errors::*;
-*/
-
 /**
  * Represents a password policy
  * The letter has to appear between min and max times.
@@ -44,35 +33,40 @@ pub struct PasswordEntry {
 // Input builder
 // ---------------------------------------------------------------------------
 #[aoc_generator(day2)]
-pub fn input_gen(input: &str) -> Vec<PasswordEntry> {
-//    input.lines().map(|l| { PasswordEntry::parse_manual(l).unwrap() }).collect()
-    input.lines().map(|l| { PasswordEntry::from_str(l).unwrap() }).collect()
+pub fn input_gen(input: &str) -> Result<Vec<PasswordEntry>, ParseError> {
+    error::parse_lines(input, PasswordEntry::parse_manual)
 }
 
 // ---------------------------------------------------------------------------
 // Policy checker
 // ---------------------------------------------------------------------------
+fn unexpected(expected: &'static str, found: &str, column: usize) -> ParseError {
+    ParseError::new(DAY, ParseErrorKind::Unexpected{expected, found: String::from(found)})
+        .at_column(column)
+}
+
 impl Policy {
 
-    // Manual parsing -- this is brittle and ugly
-    fn parse_manual(input: &str) -> Result<Policy, Box<dyn Error>> {
-        let dash = input.find('-').unwrap();
-        let space = input.find(' ').unwrap();
-        let min = usize::from_str(&input[0..dash])?;
-        let max = usize::from_str(&input[dash+1..space])?;
-        let letter: char = char::from_str(&input[space+1..])?;
+    // Manual parsing, reporting the column of the faulty part
+    fn parse_manual(input: &str) -> Result<Policy, ParseError> {
+        let dash = input.find('-').ok_or_else(|| unexpected("`min-max letter`", input, 1))?;
+        let space = dash + input[dash..].find(' ').ok_or_else(|| unexpected("`min-max letter`", input, 1))?;
+        let min = error::parse_number(DAY, &input[0..dash]).map_err(|e| e.at_column(1))?;
+        let max = error::parse_number(DAY, &input[dash+1..space]).map_err(|e| e.at_column(dash + 2))?;
+        let letter = char::from_str(&input[space+1..]).map_err(|_| unexpected("a single letter", &input[space+1..], space + 2))?;
         Ok(Policy{min, max, letter})
     }
 }
 
 impl PasswordEntry {
 
-    // Manual parsing -- this is brittle and ugly
-    fn parse_manual(input: &str) -> Result<PasswordEntry, Box<dyn Error>> {
-        let colon = input.find(':').unwrap();
+    // Manual parsing, reporting the column of the faulty part
+    fn parse_manual(input: &str) -> Result<PasswordEntry, ParseError> {
+        let colon = input.find(':').ok_or_else(|| unexpected("`policy: password`", input, 1))?;
         let policy = Policy::parse_manual(&input[0..colon])?;
-        let password = String::from(&input[colon+2..]);
-        Ok(PasswordEntry{policy, password})
+        let password = input[colon+1..].strip_prefix(' ')
+            .ok_or_else(|| unexpected("a space after `:`", &input[colon+1..], colon + 2))?;
+        Ok(PasswordEntry{policy, password: String::from(password)})
     }
 }
 
@@ -85,9 +79,10 @@ impl PasswordEntry {
 
     // Validation for the second part
     pub fn valid_new(&self) -> bool {
-        let first = self.password.chars().nth(self.policy.min-1).unwrap() == self.policy.letter;
-        let second = self.password.chars().nth(self.policy.max-1).unwrap() == self.policy.letter;
-        (first || second) && !(first && second)
+        // Positions are 1-based, a position outside the password never matches
+        let at = |pos: usize| pos.checked_sub(1)
+            .and_then(|index| self.password.chars().nth(index)) == Some(self.policy.letter);
+        at(self.policy.min) != at(self.policy.max)
     }
 }
 
@@ -153,20 +148,31 @@ mod tests {
 
     #[test]
     fn check_sample1() {
-        assert_eq!(PasswordEntry::parse_manual("1-3 a: abcde").unwrap().valid_old(), true);
-        assert_eq!(PasswordEntry::parse_manual("1-3 b: cdefg").unwrap().valid_old(), false);
-        assert_eq!(PasswordEntry::parse_manual("2-9 c: ccccccccc").unwrap().valid_old(), true);
-        
-        let data = input_gen("1-3 a: abcde\n1-3 b: cdefg\n2-9 c: ccccccccc");
+        assert!(PasswordEntry::parse_manual("1-3 a: abcde").unwrap().valid_old());
+        assert!(!PasswordEntry::parse_manual("1-3 b: cdefg").unwrap().valid_old());
+        assert!(PasswordEntry::parse_manual("2-9 c: ccccccccc").unwrap().valid_old());
+
+        let data = input_gen("1-3 a: abcde\n1-3 b: cdefg\n2-9 c: ccccccccc").unwrap();
         assert_eq!(part1(&data), 2);
     }
 
     #[test]
     fn check_sample2() {
-        assert_eq!(PasswordEntry::parse_manual("1-3 a: abcde").unwrap().valid_new(), true);
-        assert_eq!(PasswordEntry::parse_manual("1-3 b: cdefg").unwrap().valid_new(), false);
-        assert_eq!(PasswordEntry::parse_manual("2-9 c: ccccccccc").unwrap().valid_new(), false);
-        let data = input_gen("1-3 a: abcde\n1-3 b: cdefg\n2-9 c: ccccccccc");
+        assert!(PasswordEntry::parse_manual("1-3 a: abcde").unwrap().valid_new());
+        assert!(!PasswordEntry::parse_manual("1-3 b: cdefg").unwrap().valid_new());
+        assert!(!PasswordEntry::parse_manual("2-9 c: ccccccccc").unwrap().valid_new());
+        let data = input_gen("1-3 a: abcde\n1-3 b: cdefg\n2-9 c: ccccccccc").unwrap();
         assert_eq!(part2(&data), 1);
     }
+
+    #[test]
+    fn check_parse_errors() {
+        let err = input_gen("1-3 a: abcde\n1-x b: cdefg").unwrap_err();
+        assert_eq!(format!("{}", err), "day2 line 2 column 3: invalid number `x`");
+        let err = input_gen("1-3 ab: abcde").unwrap_err();
+        assert_eq!(format!("{}", err), "day2 line 1 column 5: expected a single letter, found `ab`");
+        let err = input_gen("1-3 a abcde").unwrap_err();
+        assert_eq!(err.line, 1);
+        assert_eq!(err.column, Some(1));
+    }
 }
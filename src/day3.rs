@@ -3,17 +3,22 @@
  */
 use parse_display::{Display, FromStr};
 use std::fmt;
+use std::fmt::Write as _;
+use std::ops::RangeInclusive;
 use std::str;
+use crate::error::ParseError;
+use crate::grid::{Cell, Grid};
+
+const DAY: u32 = 3;
 
 // ---------------------------------------------------------------------------
 // Data types
 // ---------------------------------------------------------------------------
 
 // The map is a rectangular array of unknown dimensions.
-// It is stored as a Vector of rows.
-// Each row is a Vector of locations, empty or with a tree
-#[derive(Debug, Display, FromStr, PartialEq, Eq)]
-enum Location {
+// Each location is empty or with a tree
+#[derive(Clone, Copy, Debug, Display, FromStr, PartialEq, Eq)]
+pub enum Location {
     #[display(".")]
     Empty,
     #[display("#")]
@@ -21,13 +26,17 @@ enum Location {
 }
 
 #[derive(Debug)]
-struct Row {
-    data: Vec<Location>
+pub struct Area {
+    map: Grid<Location>,
 }
 
-#[derive(Debug)]
-struct Area {
-    map: Vec<Row>,
+/** Trees hit going down a slope */
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Slope {
+    pub right: usize,
+    pub down: usize,
+    /** (row, column) of each tree, the column within the map */
+    pub hits: Vec<(usize, usize)>,
 }
 
 // ---------------------------------------------------------------------------
@@ -39,93 +48,116 @@ impl fmt::Display for OutOfMapError {
     }
 }
 
-
-
-impl fmt::Display for Row {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        for l in self.data.iter() {
-            write!(f, "{}", l)?;
+impl Cell for Location {
+    const DAY: u32 = DAY;
+    fn from_char(input: char) -> Option<Self> {
+        match input {
+            '#' => Some(Location::Tree),
+            '.' => Some(Location::Empty),
+            _ => None,
         }
-        Ok(())
-    }
-}
-
-impl Location {
-    fn from_char(input: char) -> Result<Self, &'static str> {
-        let loc = match input {
-            '#' => Location::Tree,
-            '.' => Location::Empty,
-            _ => return Err("unknown char")
-        };
-        Ok(loc)
-    }
-}
-
-impl str::FromStr for Row {
-    type Err = std::char::ParseCharError;
-    fn from_str(input: &str) -> Result<Self, Self::Err> {
-        let mut data = Vec::new();
-        for c in input.chars() {
-            data.push(Location::from_char(c).unwrap());
-        }
-        Ok(Row{data})
-    }
-}
-
-impl Row {
-    pub fn get(&self, y: usize) -> &Location {
-        let index = y % self.data.len();
-        &self.data[index]
     }
 }
 
 impl str::FromStr for Area {
-    type Err = std::char::ParseCharError;
+    type Err = ParseError;
     fn from_str(input: &str) -> Result<Self, Self::Err> {
-        let mut map = Vec::new();
-        for line in input.lines() {
-            map.push(line.parse::<Row>().unwrap());
-        }
-        let area = Area{map};
-        println!("New {} x {} map", area.size().0, area.size().1);
+        // Empty rows would make the horizontal rollover meaningless: the grid rejects them
+        let area = Area{map: input.parse()?};
+        trace!(Info, "New {} x {} map", area.size().0, area.size().1);
         Ok(area)
     }
 }
 
 impl Area {
-    /** New empty map */
-    pub fn new() -> Area {
-        Area{map:Vec::new()}
-    }
-
     /** Return (rows, columns )*/
     pub fn size(&self) -> (usize, usize) {
-        match self.map.len() {
-            0 => (0, 0),
-            _ => (self.map.len(), self.map[0].data.len())
-        }
+        self.map.size()
     }
 
     /** Get location value (with horizontal rollover) */
     pub fn get(&self, x: usize, y: usize) -> Result<&Location, OutOfMapError> {
-        if x >= self.map.len() { return Err(OutOfMapError); }
-        Ok(self.map[x].get(y))
+        self.map.get_wrapping(x, y).ok_or(OutOfMapError)
     }
 
     /** Count trees on a slope */
     pub fn count_trees(&self, right: usize, down: usize) -> usize {
-        let mut res: usize = 0;
-        let mut x: usize = 0;
-        let mut y: usize = 0;
-        loop {
-            match self.get(x, y) {
-                Ok(Location::Empty) => (),
-                Ok(Location::Tree) => res += 1,
-                Err(_e) => break res,
-            };
-            x += down;
-            y += right;
+        self.hits(right, down).len()
+    }
+
+    /**
+     * Every location visited on a slope, from the top left corner to the
+     * bottom: (row, column within the map, location).
+     * Going 0 down never ends: nothing is visited.
+     */
+    pub fn trajectory(&self, right: usize, down: usize) -> impl Iterator<Item = (usize, usize, Location)> + '_ {
+        let cols = self.size().1;
+        (0..)
+            .map(move |step| (step * down, step * right))
+            .take_while(move |(x, _)| down > 0 && *x < self.size().0)
+            .filter_map(move |(x, y)| self.get(x, y).ok().map(|location| (x, y % cols, *location)))
+    }
+
+    /** Positions of the trees on a slope */
+    pub fn hits(&self, right: usize, down: usize) -> Vec<(usize, usize)> {
+        self.trajectory(right, down)
+            .filter(|(_, _, location)| *location == Location::Tree)
+            .map(|(x, y, _)| (x, y))
+            .collect()
+    }
+
+    /**
+     * The map repeated to the right as far as the slope goes, with each
+     * visited location shown as O, or X for a tree
+     */
+    pub fn overlay(&self, right: usize, down: usize) -> String {
+        let (rows, cols) = self.size();
+        if rows == 0 || down == 0 {
+            return String::new();
+        }
+        let width = ((rows - 1) / down * right / cols + 1) * cols;
+        let mut overlay = String::with_capacity(rows * (width + 1));
+        for (x, row) in self.map.iter_rows().enumerate() {
+            // Column visited on this row, if any
+            let visited = if x % down == 0 { Some(x / down * right) } else { None };
+            for (y, location) in row.iter().cycle().take(width).enumerate() {
+                let _ = match (visited == Some(y), location) {
+                    (false, _) => write!(overlay, "{}", location),
+                    (true, Location::Empty) => write!(overlay, "O"),
+                    (true, Location::Tree) => write!(overlay, "X"),
+                };
+            }
+            overlay.push('\n');
         }
+        overlay
+    }
+
+    pub fn slope(&self, right: usize, down: usize) -> Slope {
+        Slope{right, down, hits: self.hits(right, down)}
+    }
+
+    /**
+     * Every slope in the ranges, from the fewest to the most trees.
+     * Ties are ordered by down, then right. Slopes going 0 down are skipped.
+     */
+    pub fn ranked(&self, rights: RangeInclusive<usize>, downs: RangeInclusive<usize>) -> Vec<Slope> {
+        let mut slopes: Vec<Slope> = downs.filter(|down| *down > 0)
+            .flat_map(|down| rights.clone().map(move |right| (right, down)))
+            .map(|(right, down)| self.slope(right, down))
+            .collect();
+        slopes.sort_by_key(|s| (s.trees(), s.down, s.right));
+        slopes
+    }
+
+    /** Slope of the ranges with the fewest trees */
+    pub fn best_slope(&self, rights: RangeInclusive<usize>, downs: RangeInclusive<usize>) -> Option<Slope> {
+        self.ranked(rights, downs).into_iter().next()
+    }
+}
+
+impl Slope {
+    pub fn trees(&self) -> usize {
+        self.hits.len()
     }
 }
 
@@ -133,8 +165,8 @@ impl Area {
 // Input builder
 // ---------------------------------------------------------------------------
 #[aoc_generator(day3)]
-fn input_gen(input: &str) -> Area {
-    input.parse::<Area>().unwrap()
+fn input_gen(input: &str) -> Result<Area, ParseError> {
+    input.parse::<Area>()
 }
 
 // ---------------------------------------------------------------------------
@@ -145,11 +177,12 @@ fn input_gen(input: &str) -> Area {
  */
 #[aoc(day3, part1)]
 fn part1(map: &Area) -> usize {
+    trace!(Trace, "\n{}", map.overlay(3, 1));
     map.count_trees(3, 1)
 }
 
 /**
- * Return the slope with minimum number of trees
+ * Return the product of the number of trees on five slopes
  */
 #[aoc(day3, part2)]
 fn part2(map: &Area) -> usize {
@@ -168,40 +201,27 @@ fn part2(map: &Area) -> usize {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::error::ParseErrorKind;
 
     #[test]
     fn check_location() {
         assert_eq!(".".parse(), Ok(Location::Empty));
         assert_eq!("#".parse(), Ok(Location::Tree));
-        assert_eq!(Location::from_char('.'), Ok(Location::Empty));
-        assert_eq!(Location::from_char('#'), Ok(Location::Tree));
-    }
-
-    #[test]
-    fn check_row() {
-        let row = "#.".parse::<Row>().unwrap();
-        assert_eq!(format!("{}", row), "#.");
-        assert_eq!(row.data[0], Location::Tree);
-        assert_eq!(row.data[1], Location::Empty);
-    }
-
-    #[test]
-    fn check_row_get() {
-        let row = "#.#.".parse::<Row>().unwrap();
-        assert_eq!(*row.get(0), Location::Tree);
-        assert_eq!(*row.get(2), Location::Tree);
-        assert_eq!(*row.get(4), Location::Tree);
+        assert_eq!(Location::from_char('.'), Some(Location::Empty));
+        assert_eq!(Location::from_char('#'), Some(Location::Tree));
+        assert_eq!(Location::from_char('x'), None);
     }
 
     #[test]
     fn check_map_parse() {
         let map = "#.".parse::<Area>().unwrap();
-        assert_eq!(map.map[0].data[0], Location::Tree);
+        assert_eq!(map.map[(0, 0)], Location::Tree);
+        assert_eq!(format!("{}", map.map), "#.\n");
     }
 
     #[test]
     fn check_map_size() {
-        let map = Area::new();
+        let map = "".parse::<Area>().unwrap();
         assert_eq!(map.size(), (0,0));
 
         let map = "#.".parse::<Area>().unwrap();
@@ -215,11 +235,23 @@ mod tests {
     fn check_map_get() {
         let map = "#.\n..".parse::<Area>().unwrap();
         assert_eq!(*map.get(0,0).unwrap(), Location::Tree);
+        assert_eq!(*map.get(0,4).unwrap(), Location::Tree);
         assert_eq!(*map.get(1,10).unwrap(), Location::Empty);
 
         assert_eq!(map.get(2,0).unwrap_err(), OutOfMapError);
     }
 
+    #[test]
+    fn check_map_parse_error() {
+        let err = "#.\n.x".parse::<Area>().unwrap_err();
+        assert_eq!(format!("{}", err), "day3 line 2 column 2: invalid character `x`");
+        let err = "#.\n\n..".parse::<Area>().unwrap_err();
+        assert_eq!(err.kind, ParseErrorKind::Empty);
+        assert_eq!(err.line, 2);
+        let err = "#.\n...".parse::<Area>().unwrap_err();
+        assert_eq!(err.line, 2);
+    }
+
     #[test]
     fn sample1() {
         let input = "..##.......
@@ -233,11 +265,51 @@ mod tests {
 #.##...#...
 #...##....#
 .#..#...#.#";
-        let map = input_gen(input);
+        let map = input_gen(input).unwrap();
         assert_eq!(map.count_trees(1, 1), 2);
         assert_eq!(map.count_trees(3, 1), 7);
         assert_eq!(map.count_trees(5, 1), 3);
         assert_eq!(map.count_trees(7, 1), 4);
         assert_eq!(map.count_trees(1, 2), 2);
+        assert_eq!(part2(&map), 336);
+
+        let overlay = map.overlay(3, 1);
+        assert!(overlay.starts_with("O.##.........##.........##.......\n#..O#...#..#...#...#..#...#...#..\n"));
+        assert_eq!(overlay.matches('X').count(), 7);
+        assert_eq!(overlay.matches('O').count(), 4);
+        assert!(overlay.ends_with(".#..#...#.#.#..#...#.#.#..#...X.#\n"));
+    }
+
+    #[test]
+    fn check_trajectory() {
+        let map = "#..\n.#.\n..#\n#..".parse::<Area>().unwrap();
+        let visited: Vec<(usize, usize, Location)> = map.trajectory(2, 1).collect();
+        assert_eq!(visited, vec![
+            (0, 0, Location::Tree), (1, 2, Location::Empty),
+            (2, 1, Location::Empty), (3, 0, Location::Tree),
+        ]);
+        assert_eq!(map.trajectory(1, 2).count(), 2);
+        assert_eq!(map.trajectory(1, 5).count(), 1);
+        assert_eq!(map.trajectory(1, 0).count(), 0);
+        assert_eq!(map.overlay(2, 1), "X..#..#..\n.#O.#..#.\n..#.O#..#\n#..#..X..\n");
+        assert_eq!(map.overlay(0, 2), "X..\n.#.\nO.#\n#..\n");
+        assert_eq!("".parse::<Area>().unwrap().overlay(3, 1), "");
+    }
+
+    #[test]
+    fn check_slopes() {
+        let map = "#..\n.#.\n..#\n#..".parse::<Area>().unwrap();
+        assert_eq!(map.hits(1, 1), vec![(0, 0), (1, 1), (2, 2), (3, 0)]);
+        assert_eq!(map.hits(2, 1), vec![(0, 0), (3, 0)]);
+        assert_eq!(map.hits(0, 3), vec![(0, 0), (3, 0)]);
+        assert_eq!(map.hits(1, 0), vec![]);
+        assert_eq!(map.slope(4, 1), Slope{right: 4, down: 1, hits: vec![(0, 0), (1, 1), (2, 2), (3, 0)]});
+
+        let ranked = map.ranked(0..=2, 0..=2);
+        assert_eq!(ranked.len(), 6);
+        let order: Vec<(usize, usize, usize)> = ranked.iter().map(|s| (s.right, s.down, s.trees())).collect();
+        assert_eq!(order, vec![(0, 2, 1), (1, 2, 1), (0, 1, 2), (2, 1, 2), (2, 2, 2), (1, 1, 4)]);
+        assert_eq!(map.best_slope(0..=2, 0..=2), ranked.into_iter().next());
+        assert_eq!(map.best_slope(0..=2, 0..=0), None);
     }
 }
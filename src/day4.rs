@@ -1,169 +1,146 @@
 /**
  * Day 4 - Password Processing
  */
+use std::collections::HashMap;
 use std::fmt;
+use crate::error::{ParseError, ParseErrorKind};
+
+const DAY: u32 = 4;
 
 // ---------------------------------------------------------------------------
 // Data types
 // ---------------------------------------------------------------------------
+/** How strictly passports are checked */
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Policy {
+    /** Required fields are present */
+    Presence,
+    /** Required fields are present and every field has a valid value */
+    Strict,
+}
 
+/** Entry of a passport schema */
 #[derive(Debug)]
-enum Item {
-    BirthYear(String),
-    IssueYear(String),
-    ExpirationYear(String),
-    Height(String),
-    HairColor(String),
-    EyeColor(String),
-    PassportID(String),
-    CountryID(String),
+struct Field {
+    key: &'static str,
+    required: bool,
+    validator: fn(&str) -> bool,
 }
 
+/** Passport fields, in the order they are displayed */
+static SCHEMA: &[Field] = &[
+    Field{key: "byr", required: true, validator: |v| valid_year(v, 1920, 2002)},
+    Field{key: "iyr", required: true, validator: |v| valid_year(v, 2010, 2020)},
+    Field{key: "eyr", required: true, validator: |v| valid_year(v, 2020, 2030)},
+    Field{key: "hgt", required: true, validator: valid_height},
+    Field{key: "hcl", required: true, validator: valid_hair_color},
+    Field{key: "ecl", required: true, validator: valid_eye_color},
+    Field{key: "pid", required: true, validator: valid_id},
+    Field{key: "cid", required: false, validator: |_| true},
+];
 
-
+/** `key:value` items of a passport, keys belong to the schema */
 #[derive(Debug)]
 struct Passport {
-    byr: Option<Item>,
-    iyr: Option<Item>,
-    eyr: Option<Item>,
-    hgt: Option<Item>,
-    hcl: Option<Item>,
-    ecl: Option<Item>,
-    pid: Option<Item>,
-    cid: Option<Item>,
+    /** Schema the passport was parsed with, gives the display order */
+    schema: &'static [Field],
+    fields: HashMap<&'static str, String>,
 }
 
 // ---------------------------------------------------------------------------
 // Implementations
 // ---------------------------------------------------------------------------
-impl fmt::Display for Item {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match self {
-            Item::BirthYear(e) => write!(f, "byr:{}", e),
-            Item::IssueYear(e) => write!(f, "iyr:{}", e),
-            Item::ExpirationYear(e) => write!(f, "eyr:{}", e),
-            Item::Height(e) => write!(f, "hgt:{}", e),
-            Item::HairColor(e) => write!(f, "hcl:{}", e),
-            Item::EyeColor(e) => write!(f, "ecl:{}", e),
-            Item::PassportID(e) => write!(f, "pid:{}", e),
-            Item::CountryID(e) => write!(f, "cid:{}", e),
+fn valid_number(value: &str, min: u32, max: u32) -> bool {
+    value.parse::<u32>().is_ok_and(|num| (min..=max).contains(&num))
+}
+
+fn valid_year(value: &str, min: u32, max: u32) -> bool {
+    value.len() == 4 && valid_number(value, min, max)
+}
+
+fn valid_height(value: &str) -> bool {
+    if let Some(height) = value.strip_suffix("cm") {
+        valid_number(height, 150, 193)
+    } else if let Some(height) = value.strip_suffix("in") {
+        valid_number(height, 59, 76)
+    } else {
+        false
+    }
+}
+
+fn valid_hair_color(value: &str) -> bool {
+    match value.strip_prefix('#') {
+        Some(hex) => hex.len() == 6 && hex.chars().all(|c| c.is_ascii_hexdigit()),
+        None => false,
+    }
+}
+
+fn valid_eye_color(value: &str) -> bool {
+    matches!(value, "amb" | "blu" | "brn" | "gry" | "grn" | "hzl" | "oth")
+}
+
+fn valid_id(value: &str) -> bool {
+    value.len() == 9 && value.chars().all(|c| c.is_ascii_digit())
+}
+
+impl Field {
+    fn check(&self, value: Option<&String>, policy: Policy) -> bool {
+        match (value, policy) {
+            (None, _) => !self.required,
+            (Some(_), Policy::Presence) => true,
+            (Some(value), Policy::Strict) => (self.validator)(value),
         }
     }
 }
 
 impl fmt::Display for Passport {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        fn fmt_item(item: &Option<Item>, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-            match item {
-                Some(e) => write!(f, "{} ", e),
-                None => write!(f, "_ "),
+        write!(f, "Passport > ")?;
+        for field in self.schema.iter() {
+            match self.fields.get(field.key) {
+                Some(value) => write!(f, "{}:{} ", field.key, value)?,
+                None => write!(f, "_ ")?,
             }
         }
-        write!(f, "Passport > ")?;
-        fmt_item(&self.byr, f)?;
-        fmt_item(&self.iyr, f)?;
-        fmt_item(&self.eyr, f)?;
-        fmt_item(&self.hgt, f)?;
-        fmt_item(&self.hcl, f)?;
-        fmt_item(&self.ecl, f)?;
-        fmt_item(&self.pid, f)?;
-        fmt_item(&self.cid, f)?;
         Ok(())
     }
 }
 
 impl Passport {
+    /** Check every field of the schema of the passport against the policy */
+    fn valid(&self, policy: Policy) -> bool {
+        self.schema.iter().all(|field| field.check(self.fields.get(field.key), policy))
+    }
+
     /**
-     * Valid only if all fields are present (cid is optional)
+     * Parse a passport spread over several lines, keeping the fields of the schema.
+     * Errors are located relative to the first line.
      */
-    fn valid(&self) -> bool {
-        fn valid_year(value: &str, min: u32, max: u32) -> bool {
-            let num = value.parse::<u32>().unwrap();
-            value.len() == 4 && num >= min && num <= max
-        }
-        fn valid_height(value: &str) -> bool {
-            if value.ends_with("cm") {
-                let height = value.trim_end_matches("cm").parse::<u32>().unwrap();
-                height >= 150 && height <= 193
-            } else if value.ends_with("in") {
-                let height = value.trim_end_matches("in").parse::<u32>().unwrap();
-                height >= 59 && height <= 76
-            } else {
-                false
-            }
-        }
-        fn valid_hair_color(value: &str) -> bool {
-            if value.len() == 7 && value.starts_with("#") {
-                value.trim_start_matches("#")
-                    .chars()
-                    .filter(|c| c.is_ascii_hexdigit())
-                    .count() == 6
-            } else {
-                false
-            }
-        }
-        fn valid_eye_color(value: &str) -> bool {
-            match value {
-                "amb" => true,
-                "blu" => true,
-                "brn" => true,
-                "gry" => true,
-                "grn" => true,
-                "hzl" => true,
-                "oth" => true,
-                _ => false
-            }
-        }
-        fn valid_id(value: &str) -> bool {
-            value.len() == 9 && value.chars()
-                .filter(|c| c.is_ascii_digit())
-                .count() == 9
+    fn parse(input: &str, schema: &'static [Field]) -> Result<Passport, ParseError> {
+        let mut p = Passport{schema, fields: HashMap::new()};
+        trace!(Trace, "{}", input);
+        for (index, line) in input.lines().enumerate() {
+            p.parse_line(line).map_err(|e| e.at_line(index + 1))?;
         }
-
-        fn valid_item(item: &Option<Item>) -> bool {
-            match item {
-                None => false,
-                Some(Item::BirthYear(e)) => valid_year(e, 1920, 2002),
-                Some(Item::IssueYear(e)) => valid_year(e, 2010, 2020),
-                Some(Item::ExpirationYear(e)) => valid_year(e, 2020, 2030),
-                Some(Item::Height(e)) => valid_height(e),
-                Some(Item::HairColor(e)) => valid_hair_color(e),
-                Some(Item::EyeColor(e)) => valid_eye_color(e),
-                Some(Item::PassportID(e)) => valid_id(e),
-                Some(Item::CountryID(_)) => true
-            }
-        }
-        valid_item(&self.byr) &&
-            valid_item(&self.iyr) &&
-            valid_item(&self.eyr) &&
-            valid_item(&self.hgt) &&
-            valid_item(&self.hcl) &&
-            valid_item(&self.ecl) &&
-            valid_item(&self.pid)
+        Ok(p)
     }
 
-    fn parse(input: &String) -> Passport {
-        // str::split_whitespace
-        let mut p = Passport{byr:None, iyr:None, eyr:None, hgt:None, hcl:None, ecl:None, pid:None, cid:None};
-        println!("{}", input);
-        for item in input.split_whitespace() {
-            let components: Vec<&str> = item.split(':').collect();
-            let key = components[0];
-            let value = components[1];
-            //println!("{} | {} {}", item, key, value);
-            match key {
-                "byr" => p.byr = Some(Item::BirthYear(String::from(value))),
-                "iyr" => p.iyr = Some(Item::IssueYear(String::from(value))),
-                "eyr" => p.eyr = Some(Item::ExpirationYear(String::from(value))),
-                "hgt" => p.hgt = Some(Item::Height(String::from(value))),
-                "hcl" => p.hcl = Some(Item::HairColor(String::from(value))),
-                "ecl" => p.ecl = Some(Item::EyeColor(String::from(value))),
-                "pid" => p.pid = Some(Item::PassportID(String::from(value))),
-                "cid" => p.cid = Some(Item::CountryID(String::from(value))),
-                _ => ()
+    /** Parse the `key:value` items of one line, the keys out of the schema are ignored */
+    fn parse_line(&mut self, line: &str) -> Result<(), ParseError> {
+        for item in line.split_whitespace() {
+            // Items are ASCII separated by spaces: locate them by address
+            let column = item.as_ptr() as usize - line.as_ptr() as usize + 1;
+            let mut components = item.splitn(2, ':');
+            let key = components.next().unwrap_or("");
+            let value = components.next()
+                .ok_or_else(|| ParseError::new(DAY, ParseErrorKind::Unexpected{
+                    expected: "`key:value`", found: String::from(item)}).at_column(column))?;
+            match self.schema.iter().find(|f| f.key == key) {
+                Some(field) => { self.fields.insert(field.key, String::from(value)); },
+                None => trace!(Debug, "column {}: `{}` is not in the schema, ignored", column, key),
             }
         }
-        p
+        Ok(())
     }
 }
 
@@ -174,24 +151,36 @@ fn empty_line(input: &str) -> bool {
     input.trim().is_empty()
 }
 
-#[aoc_generator(day4)]
-fn input_gen(input: &str) -> Vec<Passport> {
+/** Parse an entry starting at line `first` of the input */
+fn parse_entry(entry: &str, first: usize, schema: &'static [Field]) -> Result<Passport, ParseError> {
+    Passport::parse(entry, schema).map_err(|e| { let line = first + e.line - 1; e.at_line(line) })
+}
+
+/** Passports separated by empty lines, with the fields of the schema */
+fn parse_batch(input: &str, schema: &'static [Field]) -> Result<Vec<Passport>, ParseError> {
     let mut batch = Vec::new();
     let mut entry = String::new();
-    for line in input.lines() {
+    let mut first = 1;
+    for (index, line) in input.lines().enumerate() {
         // found an empty line, try to generate a Passport
         if empty_line(line) {
-            batch.push(Passport::parse(&entry));
+            batch.push(parse_entry(&entry, first, schema)?);
             entry.clear();
+            first = index + 2;
         } else {
             entry.push_str(line);
-            entry.push(' ');
+            entry.push('\n');
         }
     }
     // Don't forget last entry
-    batch.push(Passport::parse(&entry));
+    batch.push(parse_entry(&entry, first, schema)?);
 
-    batch
+    Ok(batch)
+}
+
+#[aoc_generator(day4)]
+fn input_gen(input: &str) -> Result<Vec<Passport>, ParseError> {
+    parse_batch(input, SCHEMA)
 }
 
 
@@ -199,12 +188,19 @@ fn input_gen(input: &str) -> Vec<Passport> {
 // Solvers
 // ---------------------------------------------------------------------------
 /**
- * Count the number of valid passports
+ * Count the number of passports with all the required fields
  */
 #[aoc(day4, part1)]
 fn part1(batch: &[Passport]) -> usize {
-    let valid: Vec<&Passport> = batch.iter().filter(|p| p.valid()).collect();
-    valid.len()
+    batch.iter().filter(|p| p.valid(Policy::Presence)).count()
+}
+
+/**
+ * Count the number of passports with all the required fields, all valid
+ */
+#[aoc(day4, part2)]
+fn part2(batch: &[Passport]) -> usize {
+    batch.iter().filter(|p| p.valid(Policy::Strict)).count()
 }
 
 
@@ -214,7 +210,7 @@ fn part1(batch: &[Passport]) -> usize {
 mod tests {
     use super::*;
 
-    static INPUT: &'static str = "ecl:gry pid:860033327 eyr:2020 hcl:#fffffd
+    static INPUT: &str = "ecl:gry pid:860033327 eyr:2020 hcl:#fffffd
 byr:1937 iyr:2017 cid:147 hgt:183cm
 
 iyr:2013 ecl:amb cid:350 eyr:2023 pid:028048884
@@ -230,10 +226,93 @@ iyr:2011 ecl:brn hgt:59in";
 
     #[test]
     fn check_input_gen() {
-        let passports = input_gen(INPUT);
+        let passports = input_gen(INPUT).unwrap();
         assert_eq!(passports.len(), 4);
-        for p in passports {
-            println!("{}", p);
-        }
+        assert_eq!(passports[1].to_string(),
+                   "Passport > byr:1929 iyr:2013 eyr:2023 _ hcl:#cfa07d ecl:amb pid:028048884 cid:350 ");
+    }
+
+    #[test]
+    fn check_part1() {
+        assert_eq!(part1(&input_gen(INPUT).unwrap()), 2);
+    }
+
+    #[test]
+    fn check_part2() {
+        let invalid = "eyr:1972 cid:100
+hcl:#18171d ecl:amb hgt:170 pid:186cm iyr:2018 byr:1926
+
+iyr:2019
+hcl:#602927 eyr:1967 hgt:170cm
+ecl:grn pid:012533040 byr:1946
+
+hcl:dab227 iyr:2012
+ecl:brn hgt:182cm pid:021572410 eyr:2020 byr:1992 cid:277
+
+hgt:59cm ecl:zzz
+eyr:2038 hcl:74454a iyr:2023
+pid:3556412378 byr:2007";
+        let valid = "pid:087499704 hgt:74in ecl:grn iyr:2012 eyr:2030 byr:1980
+hcl:#623a2f
+
+eyr:2029 ecl:blu cid:129 byr:1989
+iyr:2014 pid:896056539 hcl:#a97842 hgt:165cm
+
+hcl:#888785
+hgt:164cm byr:2001 iyr:2015 cid:88
+pid:545766238 ecl:hzl
+eyr:2022
+
+iyr:2010 hgt:158cm hcl:#b6652a ecl:blu byr:1944 eyr:2021 pid:093154719";
+        assert_eq!(part2(&input_gen(invalid).unwrap()), 0);
+        assert_eq!(part1(&input_gen(invalid).unwrap()), 4);
+        assert_eq!(part2(&input_gen(valid).unwrap()), 4);
+    }
+
+    #[test]
+    fn check_schema() {
+        let field = |key| SCHEMA.iter().find(|f| f.key == key).unwrap();
+        assert!((field("byr").validator)("2002"));
+        assert!(!(field("byr").validator)("2003"));
+        assert!((field("hgt").validator)("60in"));
+        assert!(!(field("hgt").validator)("190in"));
+        assert!(!(field("hgt").validator)("190"));
+        assert!((field("hcl").validator)("#123abc"));
+        assert!(!(field("hcl").validator)("#123abz"));
+        assert!(!(field("ecl").validator)("wat"));
+        assert!(!(field("pid").validator)("0123456789"));
+
+        // Custom schema: only cid is required
+        static CID: &[Field] = &[Field{key: "cid", required: true, validator: |v| v.len() == 3}];
+        let passports = parse_batch(INPUT, CID).unwrap();
+        let valid: Vec<bool> = passports.iter().map(|p| p.valid(Policy::Strict)).collect();
+        assert_eq!(valid, vec![true, true, false, false]);
+    }
+
+    #[test]
+    fn check_custom_schema() {
+        // Loyalty cards: a member number and an optional level
+        static CARD: &[Field] = &[
+            Field{key: "num", required: true, validator: valid_id},
+            Field{key: "lvl", required: false, validator: |v| matches!(v, "gold" | "silver")},
+        ];
+        let input = "lvl:gold num:012345678\n\nnum:1234\n\nlvl:silver";
+        let cards = parse_batch(input, CARD).unwrap();
+        let valid: Vec<bool> = cards.iter().map(|c| c.valid(Policy::Strict)).collect();
+        assert_eq!(valid, vec![true, false, false]);
+        assert_eq!(cards.iter().filter(|c| c.valid(Policy::Presence)).count(), 2);
+        assert_eq!(cards[0].to_string(), "Passport > num:012345678 lvl:gold ");
+        assert_eq!(input_gen(input).unwrap()[0].to_string(), "Passport > _ _ _ _ _ _ _ _ ");
+        assert!(parse_batch("byr:1937", CARD).unwrap()[0].fields.is_empty());
+    }
+
+    #[test]
+    fn check_parse_error() {
+        let err = input_gen("byr:1937 iyr:2017\n\neyr:2020\nhcl:#fffffd hgt 183cm").unwrap_err();
+        assert_eq!(format!("{}", err), "day4 line 4 column 13: expected `key:value`, found `hgt`");
+        // Unknown keys are not an error, and not a field either
+        let passports = input_gen("byr:1937 xyz:2017").unwrap();
+        assert_eq!(passports[0].fields.len(), 1);
+        assert_eq!(passports[0].fields.get("byr"), Some(&String::from("1937")));
     }
 }
@@ -2,7 +2,9 @@
 /**
  * Day 5 - Binary Boarding
  */
-use std::fmt;
+use crate::error::{self, ParseError, ParseErrorKind};
+
+const DAY: u32 = 5;
 
 // ---------------------------------------------------------------------------
 // Data types
@@ -23,13 +25,23 @@ struct Seat {
 // Implementations
 // ---------------------------------------------------------------------------
 impl Seat {
-    fn parse(input: &str) -> Seat {
-        let bin_row = input[0..7].replace("F", "0").replace("B", "1");
-        let row = u32::from_str_radix(bin_row.as_str(), 2).unwrap();
-        let bin_col = input[7..10].replace("L", "0").replace("R", "1");
-        let col = u32::from_str_radix(bin_col.as_str(), 2).unwrap();
-        //println!("{}: {} {}", input, bin_row, bin_col);
-        Seat{row, col}
+    fn parse(input: &str) -> Result<Seat, ParseError> {
+        if input.chars().count() != 10 {
+            return Err(ParseError::new(DAY, ParseErrorKind::Unexpected{
+                expected: "10 characters", found: String::from(input)}));
+        }
+        let mut row = 0;
+        let mut col = 0;
+        for (i, c) in input.chars().enumerate() {
+            match (i, c) {
+                (0..=6, 'F') => row <<= 1,
+                (0..=6, 'B') => row = (row << 1) | 1,
+                (7..=9, 'L') => col <<= 1,
+                (7..=9, 'R') => col = (col << 1) | 1,
+                _ => return Err(ParseError::new(DAY, ParseErrorKind::InvalidChar(c)).at_column(i + 1)),
+            }
+        }
+        Ok(Seat{row, col})
     }
 
     fn id(&self) -> u32 {
@@ -41,12 +53,8 @@ impl Seat {
 // Input builder
 // ---------------------------------------------------------------------------
 #[aoc_generator(day5)]
-fn input_gen(input: &str) -> Vec<Seat> {
-    let mut res = Vec::new();
-    for line in input.lines() {
-        res.push(Seat::parse(line));
-    }
-    res
+fn input_gen(input: &str) -> Result<Vec<Seat>, ParseError> {
+    error::parse_lines(input, Seat::parse)
 }
 
 // ---------------------------------------------------------------------------
@@ -72,10 +80,10 @@ fn part2(boarding_list: &[Seat]) -> usize {
     let mut min: usize = 0;
 
     for (id, present) in list.iter().enumerate() {
-        if min == 0 && *present == true {
+        if min == 0 && *present {
             min = id;
         }
-        if min != 0 && *present == false {
+        if min != 0 && !*present {
             return id;
         }
     }
@@ -96,7 +104,7 @@ mod tests {
     }
 
     fn check_parse(input: &str, row: u32, col: u32) {
-        let s = Seat::parse(input);
+        let s = Seat::parse(input).unwrap();
         assert_eq!(s.row, row);
         assert_eq!(s.col, col);
     }
@@ -107,4 +115,12 @@ mod tests {
         check_parse("FFFBBBFRRR", 14, 7);
         check_parse("BBFFBBFRLL", 102, 4);
     }
+
+    #[test]
+    fn check_parse_error() {
+        let err = input_gen("FBFBBFFRLR\nFBFBBFRRLR").unwrap_err();
+        assert_eq!(format!("{}", err), "day5 line 2 column 7: invalid character `R`");
+        let err = input_gen("FBFBBFFRL").unwrap_err();
+        assert_eq!(format!("{}", err), "day5 line 1: expected 10 characters, found `FBFBBFFRL`");
+    }
 }
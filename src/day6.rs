@@ -1,11 +1,14 @@
 /**
  * Day 6 - Custom Customs
  */
-use std::fmt;
+use crate::error::{ParseError, ParseErrorKind};
+
+const DAY: u32 = 6;
 
 // ---------------------------------------------------------------------------
 // Data types
 // ---------------------------------------------------------------------------
+#[derive(Debug)]
 struct Group {
     /** Keep list of anyone 'yes' questions */
     any: [bool; 26],
@@ -17,37 +20,41 @@ struct Group {
 // Implementations
 // ---------------------------------------------------------------------------
 impl Group {
-    fn line_to_array(line: &str) -> [bool;26] {
+    fn line_to_array(line: &str) -> Result<[bool; 26], ParseError> {
         let mut res: [bool; 26] = [false; 26];
         let a = 'a' as usize;
-        for l in line.chars().filter(|c| c.is_ascii_lowercase()) {
+        for (i, l) in line.chars().enumerate() {
+            if !l.is_ascii_lowercase() {
+                return Err(ParseError::new(DAY, ParseErrorKind::InvalidChar(l)).at_column(i + 1));
+            }
             let index = l as usize - a;
             res[index] = true;
         }
-        res
+        Ok(res)
 
     }
     /** Parse "Anyone said yes" */
-    fn parse(input: &String) -> Group {
+    fn parse(input: &str) -> Result<Group, ParseError> {
         let mut res = Group{any: [false; 26], all: [true; 26]};
-        for line in input.lines() {
-            for (i, x) in Group::line_to_array(line).iter().enumerate() {
-                if *x == true {
+        for (index, line) in input.lines().enumerate() {
+            let answers = Group::line_to_array(line).map_err(|e| e.at_line(index + 1))?;
+            for (i, x) in answers.iter().enumerate() {
+                if *x {
                     res.any[i] = true;
                 } else {
                     res.all[i] = false;
                 }
             }
         }
-        println!("Group {} -> {} {}", input, res.nb_any(), res.nb_all());
-        res
+        trace!(Debug, "Group {:?} -> {} {}", input, res.nb_any(), res.nb_all());
+        Ok(res)
     }
 
     fn nb_any(&self) -> usize {
-        self.any.iter().filter(|c| **c==true).count()
+        self.any.iter().filter(|c| **c).count()
     }
     fn nb_all(&self) -> usize {
-        self.all.iter().filter(|c| **c==true).count()
+        self.all.iter().filter(|c| **c).count()
     }
 }
 
@@ -58,24 +65,31 @@ fn empty_line(input: &str) -> bool {
     input.trim().is_empty()
 }
 
+/** Parse a group starting at line `first` of the input */
+fn parse_group(entry: &str, first: usize) -> Result<Group, ParseError> {
+    Group::parse(entry).map_err(|e| { let line = first + e.line - 1; e.at_line(line) })
+}
+
 #[aoc_generator(day6)]
-fn input_gen(input: &str) -> Vec<Group> {
+fn input_gen(input: &str) -> Result<Vec<Group>, ParseError> {
     let mut groups = Vec::new();
     let mut entry = String::new();
-    for line in input.lines() {
+    let mut first = 1;
+    for (index, line) in input.lines().enumerate() {
         // empty line -> end of group
         if empty_line(line) {
-            groups.push(Group::parse(&entry));
+            groups.push(parse_group(&entry, first)?);
             entry.clear();
+            first = index + 2;
         } else {
             entry.push_str(line);
             entry.push('\n');
         }
     }
     // Don't forget last entry
-    groups.push(Group::parse(&entry));
+    groups.push(parse_group(&entry, first)?);
 
-    groups
+    Ok(groups)
 }
 
 // ---------------------------------------------------------------------------
@@ -97,7 +111,7 @@ fn part2(groups: &[Group]) -> usize {
 #[cfg(test)]
 mod tests {
     use super::*;
-    static SAMPLE: &'static str = "abc
+    static SAMPLE: &str = "abc
 
 a
 b
@@ -115,7 +129,7 @@ b";
 
     #[test]
     fn check_sample() {
-        let groups = input_gen(SAMPLE);
+        let groups = input_gen(SAMPLE).unwrap();
         assert_eq!(groups.len(), 5);
         assert_eq!(groups[0].nb_any(), 3);
         assert_eq!(groups[1].nb_any(), 3);
@@ -129,4 +143,10 @@ b";
         assert_eq!(groups[3].nb_all(), 1);
         assert_eq!(groups[4].nb_all(), 1);
     }
+
+    #[test]
+    fn check_parse_error() {
+        let err = input_gen("abc\n\na\nB\nc").unwrap_err();
+        assert_eq!(format!("{}", err), "day6 line 4 column 1: invalid character `B`");
+    }
 }
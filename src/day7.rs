@@ -1,7 +1,14 @@
 /**
  * Day 7 - Handy Haversacks
  */
-use std::fmt;
+use crate::error::{ParseError, ParseErrorKind};
+
+pub mod explain;
+pub mod grammar;
+pub mod lint;
+use grammar::Rule;
+
+const DAY: u32 = 7;
 
 // ---------------------------------------------------------------------------
 // Data types
@@ -22,110 +29,283 @@ use std::fmt;
  * Rows depict the "contains" relation, with the weight being the number of bags.
  * Columns depict the "can be contained by" relation, weight doesn't have any 
  * meaning other than existence of the relation.
+ * BagRules keeps both, as adjacency lists: `contents` for the rows and
+ * `containers` for the columns.
+ */
+use std::cell::RefCell;
+use std::collections::{BTreeSet, HashMap};
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+/** The bag of the puzzle */
+const TARGET: &str = "shiny gold";
+
+/** Why a question about the rules has no answer */
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum BagError {
+    /** Bag never mentioned in the rules */
+    UnknownBag(String),
+    /** Bags containing each other: each one contains the next, the last one the first */
+    Cycle(Vec<String>),
+    /** More bags than an u64 can count inside this one */
+    Overflow(String),
+    /** Contents tree of this bag with more than limit nodes */
+    TreeTooLarge { bag: String, limit: usize },
+}
+
+/**
+ * The rules, with interned bag names: a bag is its index in `names`.
+ * Answers are memoized, so that the bags shared by several others are
+ * only explored once.
+ * Parsing keeps the first rule of a bag and only records the lines of the
+ * others, for the lints: `input_gen` rejects them instead.
  */
-use petgraph::Direction;
-use petgraph::graphmap::DiGraphMap;
-use petgraph::dot::{Dot, Config};
-use std::collections::HashSet;
-
-/** 3 bright white */
-#[derive(Debug)]
-struct Relation<'a> {
-    num: u32,
-    bag: &'a str,
+#[derive(Debug, Default)]
+pub struct BagRules {
+    names: Vec<String>,
+    ids: HashMap<String, usize>,
+    /** (count, bag) directly inside each bag */
+    contents: Vec<Vec<(u32, usize)>>,
+    /** Bags directly containing each bag */
+    containers: Vec<Vec<usize>>,
+    /** Lines of the rules of each bag: only the first one is used */
+    lines: Vec<Vec<usize>>,
+    /** Line of the first rule with each bag inside */
+    mentions: Vec<Option<usize>>,
+    all_containers: RefCell<HashMap<usize, BTreeSet<usize>>>,
+    totals: RefCell<Vec<Option<u64>>>,
 }
 
-/** dark orange contain 3 bright white */
-#[derive(Debug)]
-struct Rule<'a> {
-    name: &'a str,
-    bags: Vec<Relation<'a>>,
+/** State of a bag during a depth first search */
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Visit {
+    New,
+    /** On the path being explored */
+    Open,
+    Done,
 }
 
 // ---------------------------------------------------------------------------
 // Implementations
 // ---------------------------------------------------------------------------
-fn bag_name(input: &str) -> &str {
-    input.strip_suffix(" bags")
-        .unwrap_or_else(|| input.strip_suffix(" bags.")
-            .unwrap_or_else(|| input.strip_suffix(" bag")
-                    .unwrap_or_else(|| input.strip_suffix(" bag.").unwrap())))
+impl fmt::Display for BagError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BagError::UnknownBag(bag) => write!(f, "no rule mentions {} bags", bag),
+            BagError::Cycle(bags) => match bags.first() {
+                Some(first) => write!(f, "bags containing each other: {} -> {}", bags.join(" -> "), first),
+                None => write!(f, "bags containing each other"),
+            },
+            BagError::Overflow(bag) => write!(f, "too many bags inside {} bags", bag),
+            BagError::TreeTooLarge{bag, limit} =>
+                write!(f, "more than {} nodes in the contents tree of {} bags", limit, bag),
+        }
+    }
 }
 
-// "dark orange bags contain 3 bright white bags, 4 muted yellow bags."
-fn parse_line<'a>(input: &'a str) -> Rule {
-    let tokens: Vec<&str> = input.split(" contain ").collect();
-    let bag = bag_name(tokens[0]);
-    //println!("Bag: {}", bag);
+impl Error for BagError {}
 
-    let contents: Vec<&str> = tokens[1].split(", ").collect();
-    let mut rel = Vec::new();
-    for item in contents {
-        let entry: Vec<&str> = bag_name(item).splitn(2, " ").collect();
-        let num = entry[0];
-        let _bag = entry[1];
-        //println!("\t{} {}", num, _bag);
-        if num.starts_with("no") { continue; }
-        rel.push(Relation{num:num.parse().unwrap(), bag:_bag});
+impl BagRules {
+    /** Index of a bag, added if unknown */
+    fn intern(&mut self, name: &str) -> usize {
+        if let Some(id) = self.ids.get(name) {
+            return *id;
+        }
+        let id = self.names.len();
+        self.names.push(name.to_string());
+        self.ids.insert(name.to_string(), id);
+        self.contents.push(Vec::new());
+        self.containers.push(Vec::new());
+        self.lines.push(Vec::new());
+        self.mentions.push(None);
+        self.totals.get_mut().push(None);
+        id
     }
 
-    Rule{name:bag, bags:rel}
-}
+    /** Rule at a 1-based line. The other rules of the same bag are only recorded */
+    fn add_rule(&mut self, line: usize, rule: &Rule) {
+        let bag = self.intern(&rule.name);
+        self.lines[bag].push(line);
+        if self.lines[bag].len() > 1 {
+            return;
+        }
+        for item in rule.bags.iter() {
+            let inner = self.intern(&item.bag);
+            self.mentions[inner].get_or_insert(line);
+            self.contents[bag].push((item.num, inner));
+            self.containers[inner].push(bag);
+        }
+    }
+
+    /** Number of different bags */
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+
+    fn id(&self, bag: &str) -> Result<usize, BagError> {
+        self.ids.get(bag).copied().ok_or_else(|| BagError::UnknownBag(bag.to_string()))
+    }
+
+    fn names_of(&self, bags: impl IntoIterator<Item = usize>) -> Vec<&str> {
+        bags.into_iter().map(|id| self.names[id].as_str()).collect()
+    }
+
+    /** (count, bag) directly inside a bag */
+    pub fn contents(&self, bag: &str) -> Result<Vec<(u32, &str)>, BagError> {
+        let id = self.id(bag)?;
+        Ok(self.contents[id].iter().map(|(count, inner)| (*count, self.names[*inner].as_str())).collect())
+    }
+
+    /**
+     * Bags eventually containing a bag, sorted by name. The bag itself is
+     * one of them if it is part of a cycle.
+     */
+    pub fn containers_of(&self, bag: &str) -> Result<Vec<&str>, BagError> {
+        let id = self.id(bag)?;
+        let mut names = self.names_of(self.container_ids(id));
+        names.sort_unstable();
+        Ok(names)
+    }
+
+    /** Bags eventually containing a bag, memoized */
+    fn container_ids(&self, id: usize) -> BTreeSet<usize> {
+        if let Some(containers) = self.all_containers.borrow().get(&id) {
+            return containers.clone();
+        }
+        let mut found = BTreeSet::new();
+        let mut queue = vec![id];
+        while let Some(current) = queue.pop() {
+            for container in self.containers[current].iter() {
+                if found.insert(*container) {
+                    queue.push(*container);
+                }
+            }
+        }
+        self.all_containers.borrow_mut().insert(id, found.clone());
+        found
+    }
+
+    /**
+     * Depth first search of the bags inside start, without recursion.
+     * `finish` is called on each bag after all the bags inside it.
+     * Bags already Done in `visits` are not explored again.
+     */
+    fn post_order<F>(&self, start: usize, visits: &mut [Visit], mut finish: F) -> Result<(), BagError>
+        where F: FnMut(usize) -> Result<(), BagError>
+    {
+        if visits[start] != Visit::New {
+            return Ok(());
+        }
+        visits[start] = Visit::Open;
+        let mut stack = vec![(start, 0)];
+        while let Some((bag, next)) = stack.last_mut() {
+            let bag = *bag;
+            match self.contents[bag].get(*next) {
+                Some((_, inner)) => {
+                    *next += 1;
+                    match visits[*inner] {
+                        Visit::New => {
+                            visits[*inner] = Visit::Open;
+                            stack.push((*inner, 0));
+                        },
+                        Visit::Open => {
+                            let from = stack.iter().position(|(b, _)| b == inner).unwrap_or(0);
+                            let cycle = stack[from..].iter().map(|(b, _)| self.names[*b].clone()).collect();
+                            return Err(BagError::Cycle(cycle));
+                        },
+                        Visit::Done => (),
+                    }
+                },
+                None => {
+                    stack.pop();
+                    visits[bag] = Visit::Done;
+                    finish(bag)?;
+                },
+            }
+        }
+        Ok(())
+    }
+
+    /** Number of bags required inside a bag */
+    pub fn total_contents(&self, bag: &str) -> Result<u64, BagError> {
+        let id = self.id(bag)?;
+        let mut totals = self.totals.borrow_mut();
+        let mut visits: Vec<Visit> = totals.iter()
+            .map(|total| if total.is_some() { Visit::Done } else { Visit::New })
+            .collect();
+        self.post_order(id, &mut visits, |current| {
+            let overflow = || BagError::Overflow(self.names[current].clone());
+            let mut total: u64 = 0;
+            for (count, inner) in self.contents[current].iter() {
+                let inside = totals[*inner].unwrap_or(0).checked_add(1).ok_or_else(overflow)?;
+                total = inside.checked_mul(u64::from(*count))
+                    .and_then(|n| total.checked_add(n))
+                    .ok_or_else(overflow)?;
+            }
+            trace!(Debug, "{} bags in {}", total, self.names[current]);
+            totals[current] = Some(total);
+            Ok(())
+        })?;
+        Ok(totals[id].unwrap_or(0))
+    }
 
-fn all_parents<'a>(graph: &'a DiGraphMap::<&str, u32>, node: &'a str, parents: &mut HashSet<&'a str>) {
-    for dad in graph.neighbors_directed(node, Direction::Incoming) {
-        parents.insert(dad);
-        all_parents(graph, dad, parents);
+    /** Some bags containing each other, if any */
+    pub fn find_cycle(&self) -> Option<Vec<&str>> {
+        let mut visits = vec![Visit::New; self.len()];
+        for bag in 0..self.len() {
+            if let Err(BagError::Cycle(cycle)) = self.post_order(bag, &mut visits, |_| Ok(())) {
+                return Some(cycle.iter().map(|name| self.names[self.ids[name]].as_str()).collect());
+            }
+        }
+        None
     }
 }
 
-/**
- * Return the number of bags a node must contain (recursively)
- */
-fn number_of_bags<'a>(graph: &'a DiGraphMap::<&str, u32>, node: &'a str) -> u32 {
-    let mut num: u32 = 0;
-    println!("Computing for {}", node);
-    for bag in graph.neighbors_directed(node, Direction::Outgoing) {
-        println!(" - {} {}", graph.edge_weight(node, bag).unwrap(), bag);
-        num = num + graph.edge_weight(node, bag).unwrap() * ( 1 + number_of_bags(graph, bag));
-    }
-    println!("{} bags in {}", num, node);
-    num
+impl FromStr for BagRules {
+    type Err = ParseError;
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let mut rules = BagRules::default();
+        for (line, rule) in grammar::parse_rules(input)? {
+            rules.add_rule(line, &rule);
+        }
+        trace!(Info, "{} bags", rules.len());
+        Ok(rules)
+    }
 }
 
 // ---------------------------------------------------------------------------
 // Input builder
 // ---------------------------------------------------------------------------
-//#[aoc_generator(day7)]
-fn input_gen(input: &str) -> DiGraphMap::<&str, u32> {
-    let num = input.lines().count();
-    //println!("Input contains {} lines", num);
-    let mut bags = DiGraphMap::<&str, u32>::new();
-    for line in input.lines() {
-        let rule = parse_line(line);
-        //println!("{:?}", rule);
-        for item in rule.bags.iter() {
-            bags.add_edge(rule.name, item.bag, item.num);
-        }
+/** Rules of the puzzle: several rules for a bag make the answers ambiguous */
+#[aoc_generator(day7)]
+fn input_gen(input: &str) -> Result<BagRules, ParseError> {
+    let rules: BagRules = input.parse()?;
+    let duplicate = (0..rules.len())
+        .filter_map(|id| rules.lines[id].get(1).map(|line| (*line, id)))
+        .min();
+    match duplicate {
+        Some((line, id)) =>
+            Err(ParseError::new(DAY, ParseErrorKind::DuplicateRule(rules.names[id].clone())).at_line(line)),
+        None => Ok(rules),
     }
-    bags
 }
 
 // ---------------------------------------------------------------------------
 // Solvers
 // ---------------------------------------------------------------------------
 #[aoc(day7, part1)]
-fn part1(input: &str) -> usize {
-    let graph = input_gen(input);
-    let mut parents = HashSet::new();
-    all_parents(&graph, "shiny gold", &mut parents);
-    parents.len()
+fn part1(rules: &BagRules) -> Result<usize, BagError> {
+    Ok(rules.containers_of(TARGET)?.len())
 }
 
 #[aoc(day7, part2)]
-fn part2(input: &str) -> u32 {
-    let graph = input_gen(input);
-    number_of_bags(&graph, "shiny gold")
+fn part2(rules: &BagRules) -> Result<u64, BagError> {
+    rules.total_contents(TARGET)
 }
 
 // ---------------------------------------------------------------------------
@@ -134,7 +314,7 @@ fn part2(input: &str) -> u32 {
 mod tests {
     use super::*;
 
-    static INPUT: &'static str = "light red bags contain 1 bright white bag, 2 muted yellow bags.
+    static INPUT: &str = "light red bags contain 1 bright white bag, 2 muted yellow bags.
 dark orange bags contain 3 bright white bags, 4 muted yellow bags.
 bright white bags contain 1 shiny gold bag.
 muted yellow bags contain 2 shiny gold bags, 9 faded blue bags.
@@ -144,7 +324,7 @@ vibrant plum bags contain 5 faded blue bags, 6 dotted black bags.
 faded blue bags contain no other bags.
 dotted black bags contain no other bags.";
 
-    static INPUT2: &'static str = "shiny gold bags contain 2 dark red bags.
+    static INPUT2: &str = "shiny gold bags contain 2 dark red bags.
 dark red bags contain 2 dark orange bags.
 dark orange bags contain 2 dark yellow bags.
 dark yellow bags contain 2 dark green bags.
@@ -154,24 +334,98 @@ dark violet bags contain no other bags.";
 
     #[test]
     fn test_input_gen() {
-        let graph = input_gen(INPUT);
-        println!("{:?}", Dot::new(&graph));
+        let rules = input_gen(INPUT).unwrap();
+        assert_eq!(9, rules.len());
+        assert_eq!(Ok(vec![(1, "dark olive"), (2, "vibrant plum")]), rules.contents("shiny gold"));
+        assert_eq!(Ok(vec![]), rules.contents("faded blue"));
+        assert_eq!(None, rules.find_cycle());
     }
+
     #[test]
     fn test_sample() {
-        let graph = input_gen(INPUT);
-        let mut parents = HashSet::new();
-        all_parents(&graph, "shiny gold", &mut parents);
-        println!("{:?}", parents);
-        assert_eq!(4, parents.len());
+        let rules = input_gen(INPUT).unwrap();
+        let containers = rules.containers_of("shiny gold").unwrap();
+        assert_eq!(vec!["bright white", "dark orange", "light red", "muted yellow"], containers);
+        assert_eq!(Ok(containers), rules.containers_of("shiny gold"));
+        assert_eq!(Ok(4), part1(&rules));
+        assert_eq!(Ok(vec![]), rules.containers_of("light red"));
+        assert_eq!(Ok(32), part2(&rules));
+        assert_eq!(Ok(7), rules.total_contents("dark olive"));
+        assert_eq!(Err(BagError::UnknownBag(String::from("plaid"))), rules.total_contents("plaid"));
     }
 
     #[test]
     fn test_sample2() {
-        let graph = input_gen(INPUT2);
-        let num = number_of_bags(&graph, "shiny gold");
-        assert_eq!(126, num);
+        let rules = input_gen(INPUT2).unwrap();
+        assert_eq!(Ok(126), rules.total_contents("shiny gold"));
+        assert_eq!(Ok(0), rules.total_contents("dark violet"));
     }
-}
 
+    /** Bag names made of letters: xa, xb, ..., xz, xba, xbb... */
+    pub(super) fn bag(mut i: usize) -> String {
+        let mut name = Vec::new();
+        loop {
+            name.push(b'a' + (i % 26) as u8);
+            i /= 26;
+            if i == 0 {
+                break;
+            }
+        }
+        name.reverse();
+        format!("light x{}", String::from_utf8(name).unwrap())
+    }
+
+    #[test]
+    fn test_deep_rules() {
+        // Long enough to overflow the stack of a recursive search
+        let chain: String = (0..100_000)
+            .map(|i| format!("{} bags contain 1 {} bag.\n", bag(i), bag(i + 1)))
+            .collect();
+        let rules = input_gen(&chain).unwrap();
+        assert_eq!(Ok(100_000), rules.total_contents(&bag(0)));
+        assert_eq!(Ok(99_999), rules.total_contents(&bag(1)));
+        assert_eq!(100_000, rules.containers_of(&bag(100_000)).unwrap().len());
+
+        let chain: String = (0..20)
+            .map(|i| format!("{} bags contain 1000 {} bags.\n", bag(i), bag(i + 1)))
+            .collect();
+        let rules = input_gen(&chain).unwrap();
+        assert_eq!(Err(BagError::Overflow(bag(13))), rules.total_contents(&bag(0)));
+    }
 
+    #[test]
+    fn test_cycle() {
+        let rules = input_gen("a bags contain 1 b bag.
+b bags contain 2 c bags, 1 d bag.
+c bags contain 1 a bag.
+d bags contain no other bags.").unwrap();
+        let cycle = vec![String::from("a"), String::from("b"), String::from("c")];
+        assert_eq!(Err(BagError::Cycle(cycle)), rules.total_contents("a"));
+        assert_eq!(Some(vec!["a", "b", "c"]), rules.find_cycle());
+        assert_eq!(Ok(vec!["a", "b", "c"]), rules.containers_of("a"));
+        assert_eq!(Ok(0), rules.total_contents("d"));
+        assert_eq!(format!("{}", BagError::Cycle(vec![String::from("a"), String::from("b")])),
+                   "bags containing each other: a -> b -> a");
+        assert_eq!(format!("{}", BagError::Cycle(vec![])), "bags containing each other");
+    }
+
+    #[test]
+    fn test_parse_error() {
+        let err = input_gen("shiny gold bags contain 2 dark red bags.\ndark red bags contain two dark blue bags.").unwrap_err();
+        assert_eq!(format!("{}", err), "day7 line 2 column 23: invalid number `two`");
+        let err = input_gen("shiny gold bags contain 2 dark red boxes.").unwrap_err();
+        assert_eq!(format!("{}", err), "day7 line 1 column 41: expected `bag` or `bags`, found `.`");
+        let err = input_gen("shiny gold bags").unwrap_err();
+        assert_eq!(err.column, Some(16));
+        // Windows line endings and blank lines
+        let rules = input_gen(&INPUT2.replace('\n', "\r\n\r\n")).unwrap();
+        assert_eq!(Ok(126), rules.total_contents("shiny gold"));
+
+        // Only the generator of the solvers rejects a second rule
+        let twice = "a bags contain 1 b bag.\nb bags contain no other bags.\na bags contain 2 c bags.";
+        let err = input_gen(twice).unwrap_err();
+        assert_eq!(format!("{}", err), "day7 line 3: duplicate rule for `a`");
+        let rules: BagRules = twice.parse().unwrap();
+        assert_eq!(Ok(vec![(1, "b")]), rules.contents("a"));
+    }
+}
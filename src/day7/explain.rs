@@ -0,0 +1,277 @@
+/**
+ * Why a bag holds so many bags
+ *
+ * The contents tree of a bag repeats the bags shared by several others, each
+ * with the number of copies required at that place: the counts multiplied
+ * from the root down. It is shown as indented text or JSON. The Graphviz
+ * export keeps each bag once, with the counts on the edges.
+ */
+use std::collections::HashMap;
+use std::fmt::Write;
+use petgraph::dot::{Config, Dot};
+use petgraph::graph::{DiGraph, NodeIndex};
+use super::{BagError, BagRules};
+
+/** Largest contents tree built: it has one node per path, so it can grow exponentially */
+pub const MAX_TREE_NODES: usize = 1_000_000;
+
+// ---------------------------------------------------------------------------
+// Data types
+// ---------------------------------------------------------------------------
+/**
+ * A bag and everything inside it. Trees are walked with explicit stacks, so
+ * that long chains of bags do not overflow the call stack.
+ */
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Tree {
+    pub bag: String,
+    /** Bags of this kind directly inside the parent bag */
+    pub num: u32,
+    /** Bags of this kind at this place, for one root bag */
+    pub count: u64,
+    /** Number of bags inside the `count` bags of this node */
+    pub total: u64,
+    pub contents: Vec<Tree>,
+}
+
+/** Node of a tree being built, with its contents so far */
+struct Frame {
+    id: usize,
+    num: u32,
+    count: u64,
+    /** Index of the next bag to add in the contents of the bag */
+    next: usize,
+    contents: Vec<Tree>,
+}
+
+// ---------------------------------------------------------------------------
+// Implementations
+// ---------------------------------------------------------------------------
+fn plural(count: u64) -> &'static str {
+    if count == 1 { "bag" } else { "bags" }
+}
+
+/** JSON string literal */
+fn quote(text: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            c if c.is_control() => { let _ = write!(quoted, "\\u{:04x}", c as u32); },
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+impl Tree {
+    /**
+     * One line per bag, indented under its container:
+     *
+     * ```text
+     * shiny gold bag: 126 bags inside
+     *   2 x dark red = 2 bags
+     *     2 x dark orange = 4 bags
+     * ```
+     */
+    pub fn text(&self) -> String {
+        let mut text = format!("{} bag: {} {} inside\n", self.bag, self.total, plural(self.total));
+        let mut stack: Vec<(&Tree, usize)> = self.contents.iter().rev().map(|tree| (tree, 1)).collect();
+        while let Some((tree, depth)) = stack.pop() {
+            let _ = writeln!(text, "{:indent$}{} x {} = {} {}", "", tree.num, tree.bag, tree.count,
+                             plural(tree.count), indent = 2 * depth);
+            stack.extend(tree.contents.iter().rev().map(|inner| (inner, depth + 1)));
+        }
+        text
+    }
+
+    /** {"bag": "...", "num": 1, "count": 1, "total": 126, "contents": [...]} on one line */
+    pub fn json(&self) -> String {
+        let mut json = String::new();
+        // None closes the contents of a node
+        let mut stack = vec![Some(self)];
+        while let Some(step) = stack.pop() {
+            let tree = match step {
+                Some(tree) => tree,
+                None => { json.push_str("]}"); continue; },
+            };
+            // A node follows the start of its parent contents, or a sibling
+            if !json.is_empty() && !json.ends_with('[') {
+                json.push_str(", ");
+            }
+            let _ = write!(json, "{{\"bag\": {}, \"num\": {}, \"count\": {}, \"total\": {}, \"contents\": [",
+                           quote(&tree.bag), tree.num, tree.count, tree.total);
+            stack.push(None);
+            stack.extend(tree.contents.iter().rev().map(Some));
+        }
+        json
+    }
+}
+
+impl Drop for Tree {
+    /** Take the nodes apart one by one, the derived drop would recurse */
+    fn drop(&mut self) {
+        let mut stack = std::mem::take(&mut self.contents);
+        while let Some(mut tree) = stack.pop() {
+            stack.append(&mut tree.contents);
+        }
+    }
+}
+
+impl BagRules {
+    /**
+     * Contents tree of a bag. It has a node for each path from the bag, so
+     * it can be much larger than the rules: at most limit nodes are built.
+     */
+    pub fn contents_tree(&self, bag: &str, limit: usize) -> Result<Tree, BagError> {
+        // Rejects the cycles, and the counts too large
+        self.total_contents(bag)?;
+        let id = self.id(bag)?;
+        let too_large = || BagError::TreeTooLarge{bag: bag.to_string(), limit};
+        if limit == 0 {
+            return Err(too_large());
+        }
+        let mut nodes = 1;
+        let mut stack = vec![Frame{id, num: 1, count: 1, next: 0, contents: Vec::new()}];
+        loop {
+            let frame = stack.last_mut().expect("the root frame returns the tree");
+            if let Some((num, inner)) = self.contents[frame.id].get(frame.next) {
+                frame.next += 1;
+                nodes += 1;
+                if nodes > limit {
+                    return Err(too_large());
+                }
+                let count = frame.count * u64::from(*num);
+                stack.push(Frame{id: *inner, num: *num, count, next: 0, contents: Vec::new()});
+                continue;
+            }
+            // Every bag inside is built: the node is complete
+            let Frame{id, num, count, contents, ..} = stack.pop().expect("the stack is not empty");
+            let total = contents.iter().map(|tree| tree.count + tree.total).sum();
+            let tree = Tree{bag: self.names[id].clone(), num, count, total, contents};
+            match stack.last_mut() {
+                Some(parent) => parent.contents.push(tree),
+                None => return Ok(tree),
+            }
+        }
+    }
+
+    /**
+     * Graph of the bags inside a bag, in the Graphviz format. Nodes show the
+     * number of bags inside, edges the number of bags directly inside.
+     */
+    pub fn dot(&self, bag: &str) -> Result<String, BagError> {
+        self.total_contents(bag)?;
+        let start = self.id(bag)?;
+        let mut graph: DiGraph<&str, u32> = DiGraph::new();
+        let mut nodes: HashMap<usize, NodeIndex> = HashMap::new();
+        nodes.insert(start, graph.add_node(&self.names[start]));
+        let mut queue = vec![start];
+        while let Some(current) = queue.pop() {
+            for (num, inner) in self.contents[current].iter() {
+                let node = *nodes.entry(*inner).or_insert_with(|| {
+                    queue.push(*inner);
+                    graph.add_node(&self.names[*inner])
+                });
+                graph.add_edge(nodes[&current], node, *num);
+            }
+        }
+
+        let totals = self.totals.borrow();
+        let node_attr = |_, (_, name): (NodeIndex, &&str)| {
+            let total = self.ids.get(*name).and_then(|id| totals[*id]).unwrap_or(0);
+            format!("label = \"{}\\n{} inside\"", name, total)
+        };
+        let edge_attr = |_, _| String::new();
+        let config = [Config::NodeNoLabel];
+        Ok(format!("{}", Dot::with_attr_getters(&graph, &config, &edge_attr, &node_attr)))
+    }
+}
+
+// ---------------------------------------------------------------------------
+// ---------------------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::tests::bag;
+
+    static INPUT: &str = "shiny gold bags contain 1 dark olive bag, 2 vibrant plum bags.
+dark olive bags contain 3 faded blue bags, 4 dotted black bags.
+vibrant plum bags contain 5 faded blue bags, 6 dotted black bags.
+faded blue bags contain no other bags.
+dotted black bags contain no other bags.";
+
+    #[test]
+    fn check_tree() {
+        let rules: BagRules = INPUT.parse().unwrap();
+        let tree = rules.contents_tree("shiny gold", MAX_TREE_NODES).unwrap();
+        assert_eq!(tree.total, 32);
+        assert_eq!(tree.contents[1].bag, "vibrant plum");
+        assert_eq!(tree.contents[1].contents[1],
+                   Tree{bag: String::from("dotted black"), num: 6, count: 12, total: 0, contents: vec![]});
+        assert_eq!(tree.text(), "shiny gold bag: 32 bags inside
+  1 x dark olive = 1 bag
+    3 x faded blue = 3 bags
+    4 x dotted black = 4 bags
+  2 x vibrant plum = 2 bags
+    5 x faded blue = 10 bags
+    6 x dotted black = 12 bags
+");
+        assert_eq!(rules.contents_tree("faded blue", MAX_TREE_NODES).unwrap().text(), "faded blue bag: 0 bags inside\n");
+        assert_eq!(rules.contents_tree("plaid", MAX_TREE_NODES), Err(BagError::UnknownBag(String::from("plaid"))));
+        let rules: BagRules = "a bags contain 2 b bags.\nb bags contain 1 a bag.".parse().unwrap();
+        assert!(matches!(rules.contents_tree("a", MAX_TREE_NODES), Err(BagError::Cycle(_))));
+    }
+
+    #[test]
+    fn check_deep_tree() {
+        // Long enough to overflow the stack of a recursive walk
+        let chain: String = (0..100_000)
+            .map(|i| format!("{} bags contain 1 {} bag.\n", bag(i), bag(i + 1)))
+            .collect();
+        let rules: BagRules = chain.parse().unwrap();
+        let tree = rules.contents_tree(&bag(0), MAX_TREE_NODES).unwrap();
+        assert_eq!(tree.total, 100_000);
+        assert_eq!(tree.contents[0].total, 99_999);
+        let json = tree.json();
+        assert_eq!(json.matches("\"contents\": [").count(), 100_001);
+        assert!(json.ends_with(&format!("\"total\": 0, \"contents\": [{}", "]}".repeat(100_001))));
+        assert_eq!(rules.contents_tree(&bag(0), 100_000),
+                   Err(BagError::TreeTooLarge{bag: bag(0), limit: 100_000}));
+        drop(tree);
+
+        // One node per path: the number of paths grows like Fibonacci numbers
+        let ladder: String = (0..60)
+            .map(|i| format!("{} bags contain 1 {} bag, 1 {} bag.\n", bag(i), bag(i + 1), bag(i + 2)))
+            .collect();
+        let rules: BagRules = ladder.parse().unwrap();
+        let error = rules.contents_tree(&bag(0), MAX_TREE_NODES).unwrap_err();
+        assert_eq!(error.to_string(), format!("more than 1000000 nodes in the contents tree of {} bags", bag(0)));
+        assert_eq!(rules.contents_tree(&bag(55), MAX_TREE_NODES).unwrap().total, 24);
+    }
+
+    #[test]
+    fn check_json() {
+        let rules: BagRules = INPUT.parse().unwrap();
+        let json = rules.contents_tree("dark olive", MAX_TREE_NODES).unwrap().json();
+        assert_eq!(json, "{\"bag\": \"dark olive\", \"num\": 1, \"count\": 1, \"total\": 7, \"contents\": [\
+            {\"bag\": \"faded blue\", \"num\": 3, \"count\": 3, \"total\": 0, \"contents\": []}, \
+            {\"bag\": \"dotted black\", \"num\": 4, \"count\": 4, \"total\": 0, \"contents\": []}]}");
+        assert_eq!(quote("a \"b\"\\\n"), "\"a \\\"b\\\"\\\\\\u000a\"");
+    }
+
+    #[test]
+    fn check_dot() {
+        let rules: BagRules = INPUT.parse().unwrap();
+        let dot = rules.dot("shiny gold").unwrap();
+        assert!(dot.starts_with("digraph {\n"));
+        assert!(dot.contains("0 [ label = \"shiny gold\\n32 inside\"]"));
+        assert!(dot.contains("[ label = \"vibrant plum\\n11 inside\"]"));
+        assert!(dot.contains("0 -> 2 [ label = \"2\" ]"));
+        assert_eq!(dot.matches(" -> ").count(), 6);
+        let dot = rules.dot("dark olive").unwrap();
+        assert_eq!(dot.matches("inside").count(), 3);
+    }
+}
@@ -0,0 +1,315 @@
+/**
+ * Tokenizer and parser of the bag rules
+ *
+ * ```text
+ * rule     := name bag "contain" contents ["."]
+ * contents := "no" "other" bag | item ("," item)*
+ * item     := number name bag
+ * bag      := "bag" | "bags"
+ * name     := word+, words made of letters
+ * ```
+ *
+ * Any whitespace separates the words, and "bag" or "bags" are accepted
+ * whatever the count. Rules are printed back as canonical sentences:
+ * single spaces, the right plural, a final period.
+ */
+use std::fmt;
+use std::ops::Range;
+use crate::error::{self, ParseError, ParseErrorKind};
+use super::DAY;
+
+/** Byte range in the line */
+pub type Span = Range<usize>;
+
+// ---------------------------------------------------------------------------
+// Data types
+// ---------------------------------------------------------------------------
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Token<'a> {
+    Word(&'a str),
+    Comma,
+    Period,
+}
+
+/** 3 bright white */
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Relation {
+    pub num: u32,
+    pub bag: String,
+    /** From the count to "bag(s)" */
+    pub span: Span,
+}
+
+/** dark orange contain 3 bright white */
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Rule {
+    pub name: String,
+    /** Words of the name */
+    pub span: Span,
+    pub bags: Vec<Relation>,
+}
+
+struct Parser<'a> {
+    line: &'a str,
+    tokens: Vec<(Token<'a>, Span)>,
+    pos: usize,
+}
+
+// ---------------------------------------------------------------------------
+// Implementations
+// ---------------------------------------------------------------------------
+impl<'a> fmt::Display for Token<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Word(word) => write!(f, "{}", word),
+            Token::Comma => write!(f, ","),
+            Token::Period => write!(f, "."),
+        }
+    }
+}
+
+fn tokenize(line: &str) -> Vec<(Token<'_>, Span)> {
+    let mut tokens = Vec::new();
+    let mut word: Option<usize> = None;
+    for (i, c) in line.char_indices() {
+        if c.is_whitespace() || c == ',' || c == '.' {
+            if let Some(start) = word.take() {
+                tokens.push((Token::Word(&line[start..i]), start..i));
+            }
+            match c {
+                ',' => tokens.push((Token::Comma, i..i + 1)),
+                '.' => tokens.push((Token::Period, i..i + 1)),
+                _ => (),
+            }
+        } else if word.is_none() {
+            word = Some(i);
+        }
+    }
+    if let Some(start) = word {
+        tokens.push((Token::Word(&line[start..]), start..line.len()));
+    }
+    tokens
+}
+
+fn is_bag(word: &str) -> bool {
+    word == "bag" || word == "bags"
+}
+
+impl<'a> Parser<'a> {
+    fn new(line: &'a str) -> Self {
+        Parser{line, tokens: tokenize(line), pos: 0}
+    }
+
+    fn peek(&self) -> Option<Token<'a>> {
+        self.tokens.get(self.pos).map(|(token, _)| *token)
+    }
+
+    /** Span of the current token, empty at the end of the line */
+    fn span(&self) -> Span {
+        self.tokens.get(self.pos).map_or(self.line.len()..self.line.len(), |(_, span)| span.clone())
+    }
+
+    /** Error on the current token */
+    fn error(&self, kind: ParseErrorKind) -> ParseError {
+        ParseError::new(DAY, kind).at_column(self.span().start + 1)
+    }
+
+    fn unexpected(&self, expected: &'static str) -> ParseError {
+        let found = self.peek().map_or_else(|| String::from("end of line"), |token| token.to_string());
+        self.error(ParseErrorKind::Unexpected{expected, found})
+    }
+
+    fn keyword(&mut self, keyword: &str, expected: &'static str) -> Result<(), ParseError> {
+        match self.peek() {
+            Some(Token::Word(word)) if word == keyword => { self.pos += 1; Ok(()) },
+            _ => Err(self.unexpected(expected)),
+        }
+    }
+
+    /** "bag" or "bags", return where it ends */
+    fn bag(&mut self) -> Result<usize, ParseError> {
+        match self.peek() {
+            Some(Token::Word(word)) if is_bag(word) => {
+                let end = self.span().end;
+                self.pos += 1;
+                Ok(end)
+            },
+            _ => Err(self.unexpected("`bag` or `bags`")),
+        }
+    }
+
+    /** Words up to "bag(s)", joined by single spaces */
+    fn name(&mut self) -> Result<(String, Span), ParseError> {
+        let start = self.span().start;
+        let mut words = Vec::new();
+        let mut end = start;
+        while let Some(Token::Word(word)) = self.peek() {
+            if is_bag(word) || word == "contain" || !word.chars().all(char::is_alphabetic) {
+                break;
+            }
+            words.push(word);
+            end = self.span().end;
+            self.pos += 1;
+        }
+        if words.is_empty() {
+            return Err(self.unexpected("a bag name"));
+        }
+        Ok((words.join(" "), start..end))
+    }
+
+    fn item(&mut self) -> Result<Relation, ParseError> {
+        let start = self.span().start;
+        let num = match self.peek() {
+            Some(Token::Word(word)) => error::parse_number(DAY, word).map_err(|e| e.at_column(start + 1))?,
+            _ => return Err(self.unexpected("a number of bags")),
+        };
+        self.pos += 1;
+        let (bag, _) = self.name()?;
+        let end = self.bag()?;
+        Ok(Relation{num, bag, span: start..end})
+    }
+
+    fn rule(&mut self) -> Result<Rule, ParseError> {
+        let (name, span) = self.name()?;
+        self.bag()?;
+        self.keyword("contain", "`contain`")?;
+        let mut bags = Vec::new();
+        if self.peek() == Some(Token::Word("no")) {
+            self.pos += 1;
+            self.keyword("other", "`other`")?;
+            self.bag()?;
+        } else {
+            loop {
+                bags.push(self.item()?);
+                match self.peek() {
+                    Some(Token::Comma) => self.pos += 1,
+                    Some(Token::Period) | None => break,
+                    _ => return Err(self.unexpected("`,` or `.`")),
+                }
+            }
+        }
+        if self.peek() == Some(Token::Period) {
+            self.pos += 1;
+        }
+        match self.peek() {
+            None => Ok(Rule{name, span, bags}),
+            Some(_) => Err(self.unexpected("end of line")),
+        }
+    }
+}
+
+/** "dark orange bags contain 3 bright white bags, 4 muted yellow bags." */
+pub fn parse_rule(line: &str) -> Result<Rule, ParseError> {
+    Parser::new(line).rule()
+}
+
+/** Rules of a file with their 1-based line numbers, blank lines skipped */
+pub fn parse_rules(input: &str) -> Result<Vec<(usize, Rule)>, ParseError> {
+    let rules = error::parse_lines(input, |line| {
+        if line.trim().is_empty() { Ok(None) } else { parse_rule(line).map(Some) }
+    })?;
+    Ok(rules.into_iter().enumerate()
+        .filter_map(|(index, rule)| rule.map(|rule| (index + 1, rule)))
+        .collect())
+}
+
+/** Canonical form of a rule file */
+pub fn format(input: &str) -> Result<String, ParseError> {
+    Ok(parse_rules(input)?.iter().map(|(_, rule)| format!("{}\n", rule)).collect())
+}
+
+impl fmt::Display for Relation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {} {}", self.num, self.bag, if self.num == 1 { "bag" } else { "bags" })
+    }
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} bags contain ", self.name)?;
+        if self.bags.is_empty() {
+            return write!(f, "no other bags.");
+        }
+        for (i, relation) in self.bags.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{}", relation)?;
+        }
+        write!(f, ".")
+    }
+}
+
+// ---------------------------------------------------------------------------
+// ---------------------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn relation(num: u32, bag: &str, span: Span) -> Relation {
+        Relation{num, bag: String::from(bag), span}
+    }
+
+    #[test]
+    fn check_tokenize() {
+        assert_eq!(tokenize(" a  bag,1\tb.\r"), vec![
+            (Token::Word("a"), 1..2), (Token::Word("bag"), 4..7), (Token::Comma, 7..8),
+            (Token::Word("1"), 8..9), (Token::Word("b"), 10..11), (Token::Period, 11..12),
+        ]);
+        assert_eq!(tokenize(""), vec![]);
+    }
+
+    #[test]
+    fn check_parse() {
+        let rule = parse_rule("light red bags contain 1 bright white bag, 2 muted yellow bags.").unwrap();
+        assert_eq!(rule, Rule{name: String::from("light red"), span: 0..9, bags: vec![
+            relation(1, "bright white", 23..41),
+            relation(2, "muted yellow", 43..62),
+        ]});
+        let rule = parse_rule("faded blue bags contain no other bags.").unwrap();
+        assert_eq!(rule, Rule{name: String::from("faded blue"), span: 0..10, bags: vec![]});
+
+        // Slips of the pen
+        let rule = parse_rule("  shiny   gold bag contain 1 dark\tolive bags ,2 vibrant plum bag\r").unwrap();
+        assert_eq!(rule.name, "shiny gold");
+        assert_eq!(rule.span, 2..14);
+        assert_eq!(rule.bags, vec![relation(1, "dark olive", 27..44), relation(2, "vibrant plum", 46..64)]);
+    }
+
+    #[test]
+    fn check_parse_errors() {
+        let error = |line| {
+            let e = parse_rule(line).unwrap_err();
+            (e.column.unwrap_or(0), e.kind.to_string())
+        };
+        assert_eq!(error("bags contain no other bags."), (1, String::from("expected a bag name, found `bags`")));
+        assert_eq!(error("shiny gold contain 2 red bags."), (12, String::from("expected `bag` or `bags`, found `contain`")));
+        assert_eq!(error("shiny gold bags"), (16, String::from("expected `contain`, found `end of line`")));
+        assert_eq!(error("shiny gold bags contain 2 red bags;"), (31, String::from("expected `bag` or `bags`, found `bags;`")));
+        assert_eq!(error("shiny gold bags contain 2 red bags 3 blue bags."), (36, String::from("expected `,` or `.`, found `3`")));
+        assert_eq!(error("shiny gold bags contain no bags."), (28, String::from("expected `other`, found `bags`")));
+        assert_eq!(error("shiny gold bags contain 99999999999 red bags."), (25, String::from("invalid number `99999999999`")));
+        assert_eq!(error("shiny gold bags contain 2 red bags. Really."), (37, String::from("expected end of line, found `Really`")));
+        assert_eq!(error("shiny gold bags contain 2 red bags,"), (36, String::from("expected a number of bags, found `end of line`")));
+    }
+
+    #[test]
+    fn check_round_trip() {
+        let canonical = "light red bags contain 1 bright white bag, 2 muted yellow bags.
+bright white bags contain 1 shiny gold bag.
+faded blue bags contain no other bags.
+";
+        assert_eq!(format(canonical), Ok(String::from(canonical)));
+        let sloppy = "light  red bags contain 1 bright white bags , 2 muted yellow bag\r
+\r
+ bright white bag contain 1 shiny gold bags.
+faded blue bags contain no other bag";
+        assert_eq!(format(sloppy), Ok(String::from(canonical)));
+        let rules: Vec<Rule> = parse_rules(sloppy).unwrap().into_iter().map(|(_, rule)| rule).collect();
+        assert_eq!(parse_rules(sloppy).unwrap().iter().map(|(line, _)| *line).collect::<Vec<usize>>(), vec![1, 3, 4]);
+        let printed: Vec<Rule> = rules.iter().map(|r| parse_rule(&r.to_string()).unwrap()).collect();
+        let names = |rules: &[Rule]| rules.iter().map(|r| r.to_string()).collect::<Vec<String>>();
+        assert_eq!(names(&printed), names(&rules));
+        assert_eq!(format("a bags contain 1 b bag.\nb bags contain two c bags.").unwrap_err().line, 2);
+    }
+}
@@ -0,0 +1,265 @@
+/**
+ * Checks of hand-written rule sets
+ *
+ * The differences between two versions of the rules, and the mistakes that
+ * make the answers meaningless: bags without a rule or with several ones,
+ * bags playing no part in the question, bags containing each other.
+ */
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use super::{BagError, BagRules};
+
+// ---------------------------------------------------------------------------
+// Data types
+// ---------------------------------------------------------------------------
+/** Contents of a bag: (bag, count), sorted by bag */
+pub type Contents = Vec<(String, u32)>;
+
+/** Difference between two rule sets */
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Change {
+    /** Rule only in the new set */
+    Added { bag: String, contents: Contents },
+    /** Rule only in the old set */
+    Removed { bag: String, contents: Contents },
+    Changed { bag: String, old: Contents, new: Contents },
+}
+
+/** Suspicious rule set */
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Lint {
+    /** Bag inside another one, first at this line, without a rule of its own */
+    Undefined { bag: String, line: usize },
+    /** Several rules for a bag, at these lines */
+    Duplicate { bag: String, lines: Vec<usize> },
+    /** Bag neither containing the root bag nor inside it */
+    Unreachable { bag: String, root: String },
+    /** Root bag never mentioned in the rules: reachability is not checked */
+    UnknownRoot(String),
+    /** Bags containing each other */
+    Cycle(Vec<String>),
+}
+
+// ---------------------------------------------------------------------------
+// Implementations
+// ---------------------------------------------------------------------------
+/** "1 bright white bag, 2 muted yellow bags", like in the rules */
+struct Listing<'a>(&'a Contents);
+
+impl<'a> fmt::Display for Listing<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.0.is_empty() {
+            return write!(f, "no other bags");
+        }
+        for (i, (bag, num)) in self.0.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{} {} {}", num, bag, if *num == 1 { "bag" } else { "bags" })?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Change::Added{bag, contents} => write!(f, "+ {} bags contain {}", bag, Listing(contents)),
+            Change::Removed{bag, contents} => write!(f, "- {} bags contain {}", bag, Listing(contents)),
+            Change::Changed{bag, old, new} =>
+                write!(f, "~ {} bags contain {} -> {}", bag, Listing(old), Listing(new)),
+        }
+    }
+}
+
+impl fmt::Display for Lint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Lint::Undefined{bag, line} => write!(f, "line {}: no rule for {} bags", line, bag),
+            Lint::Duplicate{bag, lines} => {
+                let lines: Vec<String> = lines.iter().map(usize::to_string).collect();
+                write!(f, "lines {}: several rules for {} bags, the first one is used", lines.join(", "), bag)
+            },
+            Lint::Unreachable{bag, root} =>
+                write!(f, "{} bags neither contain nor are inside {} bags", bag, root),
+            Lint::UnknownRoot(root) => write!(f, "{}", BagError::UnknownBag(root.clone())),
+            Lint::Cycle(bags) => write!(f, "{}", BagError::Cycle(bags.clone())),
+        }
+    }
+}
+
+impl BagRules {
+    /** Contents of the bags with a rule */
+    fn rules(&self) -> BTreeMap<&str, Contents> {
+        (0..self.len())
+            .filter(|id| !self.lines[*id].is_empty())
+            .map(|id| {
+                let mut contents: Contents = self.contents[id].iter()
+                    .map(|(num, inner)| (self.names[*inner].clone(), *num))
+                    .collect();
+                contents.sort();
+                (self.names[id].as_str(), contents)
+            })
+            .collect()
+    }
+
+    /** Bags eventually inside a bag */
+    fn inside(&self, id: usize) -> BTreeSet<usize> {
+        let mut found = BTreeSet::new();
+        let mut queue = vec![id];
+        while let Some(current) = queue.pop() {
+            for (_, inner) in self.contents[current].iter() {
+                if found.insert(*inner) {
+                    queue.push(*inner);
+                }
+            }
+        }
+        found
+    }
+
+    /**
+     * Problems of the rules, for the questions about the root bag.
+     * Undefined and duplicate bags come by line, unreachable ones by name.
+     * An unknown root is reported once, instead of every bag as unreachable.
+     */
+    pub fn lints(&self, root: &str) -> Vec<Lint> {
+        let mut lints: Vec<Lint> = Vec::new();
+        let mut undefined: Vec<(usize, &str)> = (0..self.len())
+            .filter(|id| self.lines[*id].is_empty())
+            .filter_map(|id| self.mentions[id].map(|line| (line, self.names[id].as_str())))
+            .collect();
+        undefined.sort_unstable();
+        lints.extend(undefined.into_iter().map(|(line, bag)| Lint::Undefined{bag: bag.to_string(), line}));
+
+        let mut duplicates: Vec<(&Vec<usize>, &str)> = (0..self.len())
+            .filter(|id| self.lines[*id].len() > 1)
+            .map(|id| (&self.lines[id], self.names[id].as_str()))
+            .collect();
+        duplicates.sort_unstable();
+        lints.extend(duplicates.into_iter()
+            .map(|(lines, bag)| Lint::Duplicate{bag: bag.to_string(), lines: lines.clone()}));
+
+        match self.ids.get(root) {
+            Some(id) => {
+                let mut related = self.container_ids(*id);
+                related.insert(*id);
+                related.extend(self.inside(*id));
+                let mut unreachable: Vec<&str> = (0..self.len())
+                    .filter(|id| !related.contains(id))
+                    .map(|id| self.names[id].as_str())
+                    .collect();
+                unreachable.sort_unstable();
+                lints.extend(unreachable.into_iter()
+                    .map(|bag| Lint::Unreachable{bag: bag.to_string(), root: root.to_string()}));
+            },
+            None => lints.push(Lint::UnknownRoot(root.to_string())),
+        }
+
+        if let Some(cycle) = self.find_cycle() {
+            lints.push(Lint::Cycle(cycle.iter().map(|bag| bag.to_string()).collect()));
+        }
+        lints
+    }
+}
+
+/** Rules added, removed or changed from old to new, by bag name */
+pub fn diff(old: &BagRules, new: &BagRules) -> Vec<Change> {
+    let old = old.rules();
+    let mut new = new.rules();
+    let mut changes = Vec::new();
+    for (bag, contents) in old.into_iter() {
+        let bag = bag.to_string();
+        match new.remove(bag.as_str()) {
+            None => changes.push(Change::Removed{bag, contents}),
+            Some(new) if new != contents => changes.push(Change::Changed{bag, old: contents, new}),
+            Some(_) => (),
+        }
+    }
+    changes.extend(new.into_iter().map(|(bag, contents)| Change::Added{bag: bag.to_string(), contents}));
+    changes.sort_by(|a, b| a.bag().cmp(b.bag()));
+    changes
+}
+
+impl Change {
+    pub fn bag(&self) -> &str {
+        match self {
+            Change::Added{bag, ..} | Change::Removed{bag, ..} | Change::Changed{bag, ..} => bag,
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+// ---------------------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    static OLD: &str = "light red bags contain 1 bright white bag, 2 muted yellow bags.
+bright white bags contain 1 shiny gold bag.
+muted yellow bags contain 2 shiny gold bags, 9 faded blue bags.
+shiny gold bags contain 1 dark olive bag.
+dark olive bags contain 3 faded blue bags.
+faded blue bags contain no other bags.";
+
+    static NEW: &str = "light red bags contain 2 muted yellow bags, 1 bright white bag.
+bright white bags contain 2 shiny gold bags.
+shiny gold bags contain 1 dark olive bag, 2 vibrant plum bags.
+dark olive bags contain 3 faded blue bags.
+faded blue bags contain no other bags.
+dark olive bags contain 4 dotted black bags.
+posh tan bags contain 1 faded blue bag.
+muted yellow bags contain 2 shiny gold bags.";
+
+    fn contents(items: &[(&str, u32)]) -> Contents {
+        items.iter().map(|(bag, num)| (bag.to_string(), *num)).collect()
+    }
+
+    #[test]
+    fn check_diff() {
+        let old: BagRules = OLD.parse().unwrap();
+        let new: BagRules = NEW.parse().unwrap();
+        let changes = diff(&old, &new);
+        assert_eq!(changes, vec![
+            Change::Changed{bag: String::from("bright white"),
+                            old: contents(&[("shiny gold", 1)]), new: contents(&[("shiny gold", 2)])},
+            Change::Changed{bag: String::from("muted yellow"),
+                            old: contents(&[("faded blue", 9), ("shiny gold", 2)]),
+                            new: contents(&[("shiny gold", 2)])},
+            Change::Added{bag: String::from("posh tan"), contents: contents(&[("faded blue", 1)])},
+            Change::Changed{bag: String::from("shiny gold"),
+                            old: contents(&[("dark olive", 1)]),
+                            new: contents(&[("dark olive", 1), ("vibrant plum", 2)])},
+        ]);
+        assert_eq!(changes[0].to_string(), "~ bright white bags contain 1 shiny gold bag -> 2 shiny gold bags");
+        assert_eq!(changes[2].to_string(), "+ posh tan bags contain 1 faded blue bag");
+        let removed: Vec<String> = diff(&new, &old).iter().map(Change::to_string).collect();
+        assert!(removed.contains(&String::from("- posh tan bags contain 1 faded blue bag")));
+        assert_eq!(diff(&old, &old), vec![]);
+    }
+
+    #[test]
+    fn check_lints() {
+        let new: BagRules = NEW.parse().unwrap();
+        assert_eq!(OLD.parse::<BagRules>().unwrap().lints("shiny gold"), vec![]);
+        let lints = new.lints("shiny gold");
+        assert_eq!(lints, vec![
+            Lint::Undefined{bag: String::from("vibrant plum"), line: 3},
+            Lint::Duplicate{bag: String::from("dark olive"), lines: vec![4, 6]},
+            Lint::Unreachable{bag: String::from("posh tan"), root: String::from("shiny gold")},
+        ]);
+        // The second rule of a bag is ignored
+        assert!(new.contents("dotted black").is_err());
+        let messages: Vec<String> = lints.iter().map(Lint::to_string).collect();
+        assert_eq!(messages, vec![
+            "line 3: no rule for vibrant plum bags",
+            "lines 4, 6: several rules for dark olive bags, the first one is used",
+            "posh tan bags neither contain nor are inside shiny gold bags",
+        ]);
+
+        let rules: BagRules = "a bags contain 1 b bag.\nb bags contain 1 a bag.".parse().unwrap();
+        let cycle = Lint::Cycle(vec![String::from("a"), String::from("b")]);
+        assert_eq!(rules.lints("a"), vec![cycle.clone()]);
+        assert_eq!(rules.lints("c"), vec![Lint::UnknownRoot(String::from("c")), cycle]);
+        assert_eq!(rules.lints("c")[0].to_string(), "no rule mentions c bags");
+    }
+}
@@ -1,40 +1,42 @@
 /**
  * Day 8 - Handheld Halting
  */
-use std::fmt;
-use parse_display::{Display, FromStr};
+use parse_display::Display;
+use crate::console::{Halt, Machine, Operation};
+use crate::console::cfg::{Cfg, Highlight};
+use crate::error::{self, ParseError};
 
 // ---------------------------------------------------------------------------
 // Data types
 // ---------------------------------------------------------------------------
 
-#[derive(Clone, Debug, FromStr, Display)]
-enum Operation {
-    #[display("nop {0}")]
-    Nop(i32),
-    #[display("acc {0}")]
-    Acc(i32),
-    #[display("jmp {0}")]
-    Jmp(i32),
-}
-
 #[derive(Clone, Debug, Display, PartialEq)]
 enum Execution {
     #[display("Loop, acc {0}")]
-    Loop(i32),
+    Loop(i64),
     #[display("No Loop, acc {0}")]
-    NoLoop(i32),
+    NoLoop(i64),
+    #[display("{0}, acc {1}")]
+    Crash(Halt, i64),
 }
 
-// ---------------------------------------------------------------------------
-// Implementations
-// ---------------------------------------------------------------------------
+/** A single flipped instruction that makes the program terminate */
+#[derive(Clone, Debug, PartialEq)]
+struct Repair {
+    /** Index of the flipped instruction */
+    index: usize,
+    /** Accumulator when the repaired program terminates */
+    acc: i64,
+    /** Executed instructions of the repaired program, in order */
+    trace: Vec<usize>,
+}
 
 // ---------------------------------------------------------------------------
 // Input builder
 // ---------------------------------------------------------------------------
-fn input_gen(input: &str) -> Vec<Operation> {
-    input.lines().map(|l| { l.parse().unwrap() }).collect()
+#[aoc_generator(day8)]
+fn input_gen(input: &str) -> Result<Vec<Operation>, ParseError> {
+    error::parse_lines(input, str::parse)
 }
 
 // ---------------------------------------------------------------------------
@@ -42,96 +44,151 @@ fn input_gen(input: &str) -> Vec<Operation> {
 // ---------------------------------------------------------------------------
 /** Execute the program until a loop is detected. 
  * Return the accumulator value when detected. */
-fn find_loop(program: &Vec<Operation>) -> Execution {
-    let mut cnt: i32 = 0; // Counter
-    let mut acc: i32 = 0; // Accumulator
-    let mut old_pc = 0;
-    let mut pc: usize = 0; // Program Counter - index in the instruction list
-
-    let mut exec: Vec<(i32, i32)> = Vec::new(); // Store the execution context
-    for _ in program {
-        exec.push( (0,0) );
+fn find_loop(program: &[Operation]) -> Execution {
+    let mut machine = Machine::new(program);
+    match machine.run() {
+        Halt::Loop => Execution::Loop(machine.acc()),
+        Halt::Terminated => Execution::NoLoop(machine.acc()),
+        fault => Execution::Crash(fault, machine.acc()),
     }
+}
 
-    // Execute program
-    loop {
-        cnt += 1;
-        print!("{} {:?} {:?}", pc, program[pc], exec[pc]);
-        if exec[pc].0 != 0 {
-            println!(" | {:?} Found loop! acc = {}", exec[pc], acc);
-            return Execution::Loop(acc);
-        }
+/** Instructions of the infinite loop, in execution order, starting with the
+ * first executed one. Empty if the program does not loop. */
+fn loop_body(program: &[Operation]) -> Vec<usize> {
+    let mut machine = Machine::new(program);
+    if machine.run() != Halt::Loop {
+        return Vec::new();
+    }
+    let pcs: Vec<usize> = machine.history().iter().map(|s| s.pc).collect();
+    let start = pcs.iter().position(|pc| *pc == machine.pc()).unwrap_or(0);
+    pcs[start..].to_vec()
+}
 
-        exec[pc].0 = cnt;
-        exec[pc].1 = acc;
+/** Change the instruction pointed by PC into jmp or nop */
+fn mutate(program: &[Operation], pc: usize) -> Vec<Operation> {
+    let mut changed = program.to_vec();
+    changed[pc] = program[pc].flipped();
+    changed
+}
 
-        old_pc = pc;
-        match program[pc] {
-            Operation::Nop(_) => { 
-                pc += 1;
-            },
-            Operation::Acc(value) => { 
-                acc = acc + value;
-                pc += 1;
-            },
-            Operation::Jmp(value) => {
-                pc = (pc as i32 + value) as usize;
-            },
+/**
+ * Instructions from which the unmodified program terminates.
+ * Walk the control flow graph backwards from the end of the program (index len).
+ */
+fn reaches_end(program: &[Operation]) -> Vec<bool> {
+    let len = program.len();
+    let mut predecessors: Vec<Vec<usize>> = vec![Vec::new(); len + 1];
+    for (pc, op) in program.iter().enumerate() {
+        if let Some(next) = op.successor(pc, len) {
+            predecessors[next].push(pc);
         }
-        println!(" | {:?}", exec[old_pc]);
+    }
 
-        if pc >= program.len() {
-            println!("No loop found! acc {}", acc);
-            return Execution::NoLoop(acc);
+    let mut reached = vec![false; len + 1];
+    reached[len] = true;
+    let mut todo = vec![len];
+    while let Some(node) = todo.pop() {
+        for pc in predecessors[node].iter() {
+            if !reached[*pc] {
+                reached[*pc] = true;
+                todo.push(*pc);
+            }
         }
     }
+    reached
 }
 
-/** Change the instruction pointed by PC into jmp or nop */
-fn mutate(program: &Vec<Operation>, pc: usize) -> Vec<Operation> {
-    let mut changed = program.clone();
-    changed[pc] = match program[pc] {
-        Operation::Nop(value) => Operation::Jmp(value),
-        Operation::Jmp(value) => Operation::Nop(value),
-        Operation::Acc(value) => Operation::Acc(value),
+/**
+ * Find the jmp/nop to flip so that the program terminates, in linear time.
+ * Follow the original execution: the first flipped instruction landing on an
+ * instruction from which the program terminates is the repair.
+ * Return None if the program already terminates or cannot be repaired.
+ */
+fn repair(program: &[Operation]) -> Option<Repair> {
+    if Machine::new(program).run() == Halt::Terminated {
+        return None;
+    }
+    let len = program.len();
+    let reached = reaches_end(program);
+    let mut visited = vec![false; len];
+    let mut pc = 0;
+    let index = loop {
+        if pc >= len || visited[pc] {
+            return None;
+        }
+        visited[pc] = true;
+        let op = program[pc];
+        let flippable = !matches!(op, Operation::Acc(_));
+        if flippable && op.flipped().successor(pc, len).is_some_and(|next| reached[next]) {
+            break pc;
+        }
+        pc = op.successor(pc, len)?;
     };
-    changed
-}
+    trace!(Debug, "Repair: flip {} {}", index, program[index]);
 
+    let fixed = mutate(program, index);
+    let mut machine = Machine::new(&fixed);
+    match machine.run() {
+        Halt::Terminated => Some(Repair{
+            index,
+            acc: machine.acc(),
+            trace: machine.history().iter().map(|s| s.pc).collect(),
+        }),
+        _ => None,
+    }
+}
 
+/**
+ * Control flow graph of the program in the Graphviz format, showing the loop
+ * of part 1 and the instruction flipped in part 2.
+ */
+pub fn control_flow(program: &[Operation]) -> String {
+    let flipped = repair(program).map(|r| r.index);
+    // Split after the flipped instruction and at its new destination, so that
+    // its new edge goes from a block end to a block start
+    let leaders: Vec<usize> = flipped.into_iter()
+        .flat_map(|i| std::iter::once(i + 1).chain(program[i].flipped().successor(i, program.len())))
+        .collect();
+    let cfg = Cfg::with_leaders(program, &leaders);
+    cfg.dot(&Highlight{looping: loop_body(program), flipped})
+}
 
 // ---------------------------------------------------------------------------
 #[aoc(day8, part1)]
-fn part1(input: &str) -> i32 {
-    let program = input_gen(input);
-    if let Execution::Loop(value) = find_loop(&program) {
-        return value
-    } else {
-        panic!();
+fn part1(program: &[Operation]) -> Option<i64> {
+    match find_loop(program) {
+        Execution::Loop(value) => Some(value),
+        _ => None,
     }
 }
 
 #[aoc(day8, part2)]
-fn part2(input: &str) -> i32 {
-    let program = input_gen(input);
+fn part2(program: &[Operation]) -> Option<i64> {
+    repair(program).map(|r| r.acc)
+}
+
+/** Brute force: try every flip, O(n²) */
+#[aoc(day8, part2, brute)]
+fn part2_brute(program: &[Operation]) -> Option<i64> {
     for (index, op) in program.iter().enumerate() {
         let res = match op {
             Operation::Jmp(_) => {
-                let changed = mutate(&program, index);
+                let changed = mutate(program, index);
                 find_loop(&changed)
             },
             Operation::Nop(_) => {
-                let changed = mutate(&program, index);
+                let changed = mutate(program, index);
                 find_loop(&changed)
             },
             _ => Execution::Loop(0),
         };
 
         if let Execution::NoLoop(value) = res {
-            return value
+            return Some(value)
         }
     }
-    panic!();
+    None
 }
 
 // ---------------------------------------------------------------------------
@@ -139,7 +196,7 @@ fn part2(input: &str) -> i32 {
 mod tests {
     use super::*;
 
-    static INPUT: &'static str = "nop +0
+    static INPUT: &str = "nop +0
 acc +1
 jmp +4
 acc +3
@@ -151,18 +208,86 @@ acc +6";
 
     #[test]
     fn test_sample() {
-        let program = input_gen(INPUT);
+        let program = input_gen(INPUT).unwrap();
         assert_eq!(9, program.len());
-        println!("{:?}", program);
+        assert_eq!(&[Operation::Nop(0), Operation::Acc(1), Operation::Jmp(4)], &program[..3]);
 
         assert_eq!(Execution::Loop(5), find_loop(&program));
     }
     #[test]
     fn test_sample_mutate() {
-        let program = input_gen(INPUT);
+        let program = input_gen(INPUT).unwrap();
         let changed = mutate(&program, 7);
         let res = find_loop(&changed);
 
         assert_eq!(Execution::NoLoop(8), res);
     }
+
+    #[test]
+    fn test_repair() {
+        let program = input_gen(INPUT).unwrap();
+        let fix = repair(&program).unwrap();
+        assert_eq!(7, fix.index);
+        assert_eq!(8, fix.acc);
+        assert_eq!(vec![0, 1, 2, 6, 7, 8], fix.trace);
+        assert_eq!(Some(8), part2(&program));
+        assert_eq!(Some(8), part2_brute(&program));
+
+        // Already terminating
+        let program = input_gen("acc +1\nnop +5").unwrap();
+        assert_eq!(None, repair(&program));
+        let program = input_gen("nop +1\nacc +1").unwrap();
+        assert_eq!(None, repair(&program));
+    }
+
+    #[test]
+    fn test_repair_large() {
+        // acc +1 / nop +0 / jmp +1 repeated, then back to the start
+        let len = 150_000;
+        let mut program: Vec<Operation> = (0..len - 1).map(|i| match i % 3 {
+            0 => Operation::Acc(1),
+            1 => Operation::Nop(0),
+            _ => Operation::Jmp(1),
+        }).collect();
+        program.push(Operation::Jmp(1 - len as i32));
+
+        let fix = repair(&program).unwrap();
+        assert_eq!(len - 1, fix.index);
+        assert_eq!(50_000, fix.acc);
+        assert_eq!(len, fix.trace.len());
+    }
+
+    #[test]
+    fn test_control_flow() {
+        let program = input_gen(INPUT).unwrap();
+        assert_eq!(vec![1, 2, 6, 7, 3, 4], loop_body(&program));
+        let dot = control_flow(&program);
+        assert!(dot.contains("\"    6  acc +1\\l*   7  jmp -4\\l\", color = red, style = filled, fillcolor = yellow]"));
+        assert!(dot.contains("[ label = \"flip\", style = dashed, color = darkgreen]"));
+
+        // The flipped nop jumps into the middle of a block
+        let program = input_gen("nop +3\njmp -1\nacc +1\nacc +2\nacc +3").unwrap();
+        let dot = control_flow(&program);
+        assert!(dot.contains("label = \"    3  acc +2\\l    4  acc +3\\l\""));
+        assert!(dot.contains("0 -> 3 [ label = \"flip\""));
+
+        let program = input_gen("acc +1\nnop +5").unwrap();
+        assert!(loop_body(&program).is_empty());
+        assert!(!control_flow(&program).contains("red"));
+    }
+
+    #[test]
+    fn test_crash() {
+        let program = input_gen("acc +1\njmp -2").unwrap();
+        assert_eq!(Execution::Crash(Halt::NegativePc, 1), find_loop(&program));
+        assert_eq!(None, part1(&program));
+    }
+
+    #[test]
+    fn test_parse() {
+        let err = input_gen("nop +0\njnz +4").unwrap_err();
+        assert_eq!(format!("{}", err), "day8 line 2: unknown opcode `jnz`");
+        let err = input_gen("nop +0\njmp 4x").unwrap_err();
+        assert_eq!(format!("{}", err), "day8 line 2 column 5: invalid number `4x`");
+    }
 }
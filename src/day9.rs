@@ -1,93 +1,175 @@
 /**
  * Day 9 - Encoding Error
+ *
+ * The preamble length is 25, or the value of the AOC_PREAMBLE environment
+ * variable:
+ *
+ * ```text
+ * AOC_PREAMBLE=5 cargo aoc -d 9
+ * ```
  */
-use std::fmt;
-use parse_display::{Display, FromStr};
+use std::collections::HashMap;
+use std::env;
+use std::ops::RangeInclusive;
+use std::sync::OnceLock;
+use crate::error::{self, ParseError};
+
+const DAY: u32 = 9;
+
+/** Preamble length of the puzzle */
+const PREAMBLE: usize = 25;
 
 // ---------------------------------------------------------------------------
 // Data types
 // ---------------------------------------------------------------------------
+/**
+ * XMAS cipher: after the preamble, each number must be the sum of two
+ * numbers with different values among the `preamble` numbers before it
+ */
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Xmas {
+    pub preamble: usize,
+}
+
+/**
+ * The numbers breaking the rule, with their index. The window of previous
+ * numbers is kept as a multiset, each number being checked against it in
+ * O(preamble).
+ */
+pub struct Invalid<'a> {
+    list: &'a [u64],
+    preamble: usize,
+    index: usize,
+    /** Count of each value among the `preamble` numbers before index */
+    window: HashMap<u64, usize>,
+}
 
 // ---------------------------------------------------------------------------
 // Implementations
 // ---------------------------------------------------------------------------
-/** True if num is the sum of any of the numbers in previous */
-fn is_sum_of_two(previous: &[u64], num: u64) -> bool {
-    println!("Is {} sum of two in {:?}?", num, previous);
-
-    for (index, first) in previous.iter().enumerate() {
-        for second in previous[index..].iter() {
-            if num == first+second {
-                println!("True! {} = {} + {}", num, first, second);
-                return true;
+impl<'a> Invalid<'a> {
+    /** True if num is the sum of two different values of the window */
+    fn is_valid(&self, num: u64) -> bool {
+        self.window.keys().any(|first| {
+            num.checked_sub(*first)
+                .is_some_and(|second| second != *first && self.window.contains_key(&second))
+        })
+    }
+}
+
+impl<'a> Iterator for Invalid<'a> {
+    type Item = (usize, u64);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.index < self.list.len() {
+            let index = self.index;
+            let num = self.list[index];
+            self.index += 1;
+            let invalid = if index < self.preamble {
+                false
+            } else {
+                let valid = self.is_valid(num);
+                trace!(Trace, "{}: {} {}", index, num, if valid { "valid" } else { "invalid" });
+                // Slide the window
+                let old = self.list[index - self.preamble];
+                if let Some(count) = self.window.get_mut(&old) {
+                    *count -= 1;
+                    if *count == 0 {
+                        self.window.remove(&old);
+                    }
+                }
+                !valid
+            };
+            if self.preamble > 0 {
+                *self.window.entry(num).or_insert(0) += 1;
+            }
+            if invalid {
+                return Some((index, num));
             }
         }
+        None
     }
-    println!("False! No corresponding sum found");
-    false
 }
 
-/** Find first number which is NOT the sum of two */
-fn first_invalid(list: &[u64], len: usize) -> u64 {
-    for num in len .. list.len() {
-        if is_sum_of_two(&list[num-len..num], list[num]) == false {
-            return list[num]
-        }
+impl Xmas {
+    pub fn new(preamble: usize) -> Self {
+        Xmas{preamble}
+    }
+
+    /** Every number which is not the sum of two of the previous ones, with its index */
+    pub fn invalid<'a>(&self, list: &'a [u64]) -> Invalid<'a> {
+        Invalid{list, preamble: self.preamble, index: 0, window: HashMap::new()}
     }
-    0
+
+    pub fn first_invalid(&self, list: &[u64]) -> Option<u64> {
+        self.invalid(list).next().map(|(_, num)| num)
+    }
+
+    /** Sum of the smallest and largest numbers of the range adding up to the first invalid number */
+    pub fn weakness(&self, list: &[u64]) -> Option<u64> {
+        let range = contiguous_sum(list, self.first_invalid(list)?)?;
+        let found = &list[range];
+        Some(found.iter().min()? + found.iter().max()?)
+    }
+}
+
+/**
+ * Preamble length, from AOC_PREAMBLE if set. A value which is not a length
+ * is reported on stderr, once: the answers are then those for 25.
+ */
+fn preamble() -> usize {
+    static LENGTH: OnceLock<usize> = OnceLock::new();
+    *LENGTH.get_or_init(|| match env::var("AOC_PREAMBLE") {
+        Ok(value) => value.trim().parse().unwrap_or_else(|_| {
+            eprintln!("warning: AOC_PREAMBLE={}: not a length, using {}", value, PREAMBLE);
+            PREAMBLE
+        }),
+        Err(_) => PREAMBLE,
+    })
 }
 
-/** Find the contiguous numbers which sum up to num */
-fn contiguous_sum(list: &[u64], num: u64) -> (usize, usize) {
+/** Find at least two contiguous numbers which sum up to num */
+fn contiguous_sum(list: &[u64], num: u64) -> Option<RangeInclusive<usize>> {
     let mut sum: u64 = 0;
     let mut start: usize = 0;
-    let mut end: usize = 0;
 
     // Grow the slice and add to sum until we are too big.
-    // Then remove elements from the front and continue until
-    // we are smaller, then restart growing
-    loop {
-        println!("{} [{} {}]", sum, start, end);
-        if sum + list[end] == num {
-            println!("FOUND! [{} .. {}]", start, end);
-            return (start, end);
-        } else if sum + list[end] < num {
-            println!("too small - add next ({})", list[end]);
-            sum += list[end];
-            end += 1;
-        } else {
-            println!("too big - remove start ({})", list[start]);
+    // Then remove elements from the front until we are small enough
+    for (end, value) in list.iter().enumerate() {
+        sum += value;
+        while sum > num && start < end {
+            trace!(Trace, "too big - remove start ({})", list[start]);
             sum -= list[start];
             start += 1;
         }
+        trace!(Trace, "{} [{} {}]", sum, start, end);
+        if sum == num && end > start {
+            trace!(Debug, "FOUND! [{} ..= {}]", start, end);
+            return Some(start..=end);
+        }
     }
+    None
 }
 
 // ---------------------------------------------------------------------------
 // Input builder
 // ---------------------------------------------------------------------------
-fn input_gen(input: &str) -> Vec<u64> {
-    input.lines().map(|l| l.parse().unwrap()).collect()
+#[aoc_generator(day9)]
+fn input_gen(input: &str) -> Result<Vec<u64>, ParseError> {
+    error::parse_lines(input, |l| error::parse_number(DAY, l))
 }
 
 // ---------------------------------------------------------------------------
 // Solvers
 // ---------------------------------------------------------------------------
 #[aoc(day9, part1)]
-fn part1(input: &str) -> u64 {
-    let list = input_gen(input);
-    first_invalid(list.as_slice(), 25)
+fn part1(list: &[u64]) -> Option<u64> {
+    Xmas::new(preamble()).first_invalid(list)
 }
 
 #[aoc(day9, part2)]
-fn part2(input: &str) -> u64 {
-    let list = input_gen(input);
-    let num = first_invalid(list.as_slice(), 25);
-    let (start, end) = contiguous_sum(list.as_slice(), num);
-    let found = list[start..end].to_vec();
-    let small = found.iter().min().unwrap();
-    let big = found.iter().max().unwrap();
-    small + big
+fn part2(list: &[u64]) -> Option<u64> {
+    Xmas::new(preamble()).weakness(list)
 }
 
 
@@ -98,7 +180,7 @@ fn part2(input: &str) -> u64 {
 mod tests {
     use super::*;
 
-    static INPUT: &'static str = "35
+    static INPUT: &str = "35
 20
 15
 25
@@ -121,25 +203,58 @@ mod tests {
 
     #[test]
     fn test_is_sum_of_two() {
-        assert_eq!(true, is_sum_of_two(&[1,2,3,4], 5));
-        assert_eq!(false, is_sum_of_two(&[1,2,3,4], 1));
+        let xmas = Xmas::new(4);
+        assert_eq!(None, xmas.first_invalid(&[1, 2, 3, 4, 5]));
+        assert_eq!(Some(1), xmas.first_invalid(&[1, 2, 3, 4, 1]));
+        // Same value twice
+        assert_eq!(Some(8), xmas.first_invalid(&[1, 4, 4, 2, 8]));
+        assert_eq!(vec![(2, 2)], Xmas::new(2).invalid(&[1, 1, 2, 3]).collect::<Vec<_>>());
+        assert_eq!(None, Xmas::new(5).first_invalid(&[1, 2]));
+        assert_eq!(Some(1), Xmas::new(0).first_invalid(&[1, 2]));
     }
 
     #[test]
     fn test_sample() {
-        let list = input_gen(INPUT);
-        assert_eq!(127, first_invalid(list.as_slice(), 5));
+        let list = input_gen(INPUT).unwrap();
+        let xmas = Xmas::new(5);
+        assert_eq!(Some(127), xmas.first_invalid(&list));
+        assert_eq!(Some((14, 127)), xmas.invalid(&list).next());
+        assert_eq!(Some(62), xmas.weakness(&list));
+    }
+
+    #[test]
+    fn test_invalid() {
+        use rand::{Rng, SeedableRng};
+        use rand::rngs::StdRng;
+
+        /** Straightforward check of every pair */
+        fn naive(list: &[u64], preamble: usize) -> Vec<(usize, u64)> {
+            (preamble..list.len())
+                .filter(|i| {
+                    let window = &list[i - preamble..*i];
+                    !window.iter().any(|a| window.iter().any(|b| a != b && a + b == list[*i]))
+                })
+                .map(|i| (i, list[i]))
+                .collect()
+        }
+
+        let mut rng = StdRng::seed_from_u64(9);
+        for preamble in 1..8 {
+            let list: Vec<u64> = (0..300).map(|_| rng.gen_range(0..40)).collect();
+            let xmas = Xmas::new(preamble);
+            assert_eq!(naive(&list, preamble), xmas.invalid(&list).collect::<Vec<_>>(), "preamble {}", preamble);
+        }
     }
 
     #[test]
     fn test_contiguous() {
-        let list = input_gen(INPUT);
-        let (start, end) = contiguous_sum(list.as_slice(), 127);
-        assert_eq!(2, start);
-        assert_eq!(5, end);
-        let found = list[start..end].to_vec();
-        let small = found.iter().min().unwrap();
-        let big = found.iter().max().unwrap();
-        assert_eq!(62, small + big);
+        let list = input_gen(INPUT).unwrap();
+        assert_eq!(Some(2..=5), contiguous_sum(&list, 127));
+        assert_eq!(Some(0..=1), contiguous_sum(&list, 55));
+        // At least two numbers
+        assert_eq!(None, contiguous_sum(&[5, 7, 9], 7));
+        assert_eq!(Some(1..=2), contiguous_sum(&list, 35));
+        assert_eq!(None, contiguous_sum(&list, 1));
+        assert_eq!(None, contiguous_sum(&[], 1));
     }
 }
@@ -0,0 +1,133 @@
+/**
+ * Errors shared by all the input generators
+ */
+use std::error::Error;
+use std::fmt;
+
+// ---------------------------------------------------------------------------
+// Data types
+// ---------------------------------------------------------------------------
+
+/** What went wrong while parsing a line */
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ParseErrorKind {
+    /** Not a number, or out of range for its type */
+    InvalidNumber(String),
+    /** Character not allowed in a map or a code */
+    InvalidChar(char),
+    /** Unknown instruction mnemonic */
+    UnknownOpcode(String),
+    /** Reference to a label that is never defined */
+    UnknownLabel(String),
+    /** Label defined more than once */
+    DuplicateLabel(String),
+    /** Several rules for the same thing */
+    DuplicateRule(String),
+    /** Something else than expected was found */
+    Unexpected { expected: &'static str, found: String },
+    /** Input (or a line of it) is empty */
+    Empty,
+}
+
+/** Error of an input generator, located in the input file */
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParseError {
+    pub day: u32,
+    /** 1-based line number, 0 if unknown */
+    pub line: usize,
+    /** 1-based column number, if meaningful */
+    pub column: Option<usize>,
+    pub kind: ParseErrorKind,
+}
+
+// ---------------------------------------------------------------------------
+// Implementations
+// ---------------------------------------------------------------------------
+impl ParseError {
+    pub fn new(day: u32, kind: ParseErrorKind) -> Self {
+        ParseError{day, line: 0, column: None, kind}
+    }
+
+    pub fn at_line(mut self, line: usize) -> Self {
+        self.line = line;
+        self
+    }
+
+    pub fn at_column(mut self, column: usize) -> Self {
+        self.column = Some(column);
+        self
+    }
+}
+
+impl fmt::Display for ParseErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseErrorKind::InvalidNumber(s) => write!(f, "invalid number `{}`", s),
+            ParseErrorKind::InvalidChar(c) => write!(f, "invalid character `{}`", c),
+            ParseErrorKind::UnknownOpcode(s) => write!(f, "unknown opcode `{}`", s),
+            ParseErrorKind::UnknownLabel(s) => write!(f, "unknown label `{}`", s),
+            ParseErrorKind::DuplicateLabel(s) => write!(f, "duplicate label `{}`", s),
+            ParseErrorKind::DuplicateRule(s) => write!(f, "duplicate rule for `{}`", s),
+            ParseErrorKind::Unexpected{expected, found} =>
+                write!(f, "expected {}, found `{}`", expected, found),
+            ParseErrorKind::Empty => write!(f, "empty input"),
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "day{}", self.day)?;
+        if self.line != 0 {
+            write!(f, " line {}", self.line)?;
+        }
+        if let Some(column) = self.column {
+            write!(f, " column {}", column)?;
+        }
+        write!(f, ": {}", self.kind)
+    }
+}
+
+impl Error for ParseError {}
+
+// ---------------------------------------------------------------------------
+// Helpers
+// ---------------------------------------------------------------------------
+/** Parse a number, reporting the offending text on failure */
+pub fn parse_number<T: std::str::FromStr>(day: u32, input: &str) -> Result<T, ParseError> {
+    input.parse().map_err(|_| ParseError::new(day, ParseErrorKind::InvalidNumber(String::from(input))))
+}
+
+/** Parse each line of the input, tagging errors with their line number */
+pub fn parse_lines<'a, T, F>(input: &'a str, mut parse: F) -> Result<Vec<T>, ParseError>
+    where F: FnMut(&'a str) -> Result<T, ParseError>
+{
+    input.lines()
+        .enumerate()
+        .map(|(index, line)| parse(line).map_err(|e| e.at_line(index + 1)))
+        .collect()
+}
+
+// ---------------------------------------------------------------------------
+// ---------------------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_display() {
+        let e = ParseError::new(8, ParseErrorKind::UnknownOpcode(String::from("jnz"))).at_line(412);
+        assert_eq!(format!("{}", e), "day8 line 412: unknown opcode `jnz`");
+        let e = ParseError::new(3, ParseErrorKind::InvalidChar('x')).at_line(2).at_column(5);
+        assert_eq!(format!("{}", e), "day3 line 2 column 5: invalid character `x`");
+        let e = ParseError::new(11, ParseErrorKind::Empty);
+        assert_eq!(format!("{}", e), "day11: empty input");
+    }
+
+    #[test]
+    fn check_parse_lines() {
+        let res = parse_lines("1\n2\nthree\n4", |l| parse_number::<u32>(1, l));
+        assert_eq!(res, Err(ParseError::new(1, ParseErrorKind::InvalidNumber(String::from("three"))).at_line(3)));
+        assert_eq!(parse_lines("1\n2", |l| parse_number::<u32>(1, l)), Ok(vec![1, 2]));
+    }
+}
@@ -0,0 +1,276 @@
+/**
+ * Animated GIF writer
+ *
+ * Paletted frames of the same size, compressed with the GIF flavour of LZW:
+ * variable code size from the palette size + 1 bits up to 12 bits, and a
+ * clear code when the dictionary is full. Animations loop forever.
+ */
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::io::{self, Write};
+
+/** Largest LZW code size */
+const MAX_CODE_SIZE: u8 = 12;
+
+// ---------------------------------------------------------------------------
+// Data types
+// ---------------------------------------------------------------------------
+pub struct Encoder<W: Write> {
+    out: W,
+    width: u16,
+    height: u16,
+    colours: usize,
+    min_code_size: u8,
+}
+
+/** Variable length codes, packed least significant bit first */
+struct BitWriter {
+    bytes: Vec<u8>,
+    buffer: u32,
+    bits: u8,
+}
+
+// ---------------------------------------------------------------------------
+// Implementations
+// ---------------------------------------------------------------------------
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message)
+}
+
+impl BitWriter {
+    fn new() -> Self {
+        BitWriter{bytes: Vec::new(), buffer: 0, bits: 0}
+    }
+
+    fn write(&mut self, code: u16, size: u8) {
+        self.buffer |= u32::from(code) << self.bits;
+        self.bits += size;
+        while self.bits >= 8 {
+            self.bytes.push(self.buffer as u8);
+            self.buffer >>= 8;
+            self.bits -= 8;
+        }
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.bits > 0 {
+            self.bytes.push(self.buffer as u8);
+        }
+        self.bytes
+    }
+}
+
+/**
+ * LZW compression of palette indexes, as stored in a GIF image block
+ * (before the split in sub-blocks).
+ * Every index must be below 2^min_code_size.
+ */
+pub fn lzw_encode(pixels: &[u8], min_code_size: u8) -> Vec<u8> {
+    let clear: u16 = 1 << min_code_size;
+    let end = clear + 1;
+    let mut writer = BitWriter::new();
+    let mut size = min_code_size + 1;
+    let mut next = end + 1;
+    let mut dictionary: HashMap<(u16, u8), u16> = HashMap::new();
+
+    writer.write(clear, size);
+    let mut pixels = pixels.iter().copied();
+    let mut prefix = match pixels.next() {
+        Some(p) => u16::from(p),
+        None => {
+            writer.write(end, size);
+            return writer.finish();
+        },
+    };
+    for pixel in pixels {
+        if let Some(code) = dictionary.get(&(prefix, pixel)) {
+            prefix = *code;
+            continue;
+        }
+        writer.write(prefix, size);
+        // The decoder adds its entries one code late: it reads the next code
+        // with a larger size once the code before last filled the size
+        if next == 1 << size && size < MAX_CODE_SIZE {
+            size += 1;
+        }
+        if next < 1 << MAX_CODE_SIZE {
+            dictionary.insert((prefix, pixel), next);
+            next += 1;
+        } else {
+            writer.write(clear, size);
+            dictionary.clear();
+            size = min_code_size + 1;
+            next = end + 1;
+        }
+        prefix = u16::from(pixel);
+    }
+    writer.write(prefix, size);
+    if next == 1 << size && size < MAX_CODE_SIZE {
+        size += 1;
+    }
+    writer.write(end, size);
+    writer.finish()
+}
+
+impl<W: Write> Encoder<W> {
+    /**
+     * Write the header, the palette (at most 256 colours) and the loop
+     * extension. Frames follow with `frame`.
+     */
+    pub fn new(mut out: W, width: usize, height: usize, palette: &[[u8; 3]]) -> io::Result<Self> {
+        if palette.is_empty() || palette.len() > 256 {
+            return Err(invalid(format!("{} colours in the palette", palette.len())));
+        }
+        let too_large = || invalid(format!("{} x {} image", width, height));
+        let w = u16::try_from(width).map_err(|_| too_large())?;
+        let h = u16::try_from(height).map_err(|_| too_large())?;
+        // The palette holds 2^(n + 1) colours, the LZW codes at least 2 bits
+        let bits = (palette.len().next_power_of_two().trailing_zeros() as u8).max(1);
+        let min_code_size = bits.max(2);
+
+        out.write_all(b"GIF89a")?;
+        out.write_all(&w.to_le_bytes())?;
+        out.write_all(&h.to_le_bytes())?;
+        // Global colour table, 8 bits per channel
+        out.write_all(&[0x80 | 0x70 | (bits - 1), 0, 0])?;
+        for i in 0..1 << bits {
+            out.write_all(palette.get(i).unwrap_or(&[0, 0, 0]))?;
+        }
+        // Netscape extension: loop forever
+        out.write_all(b"\x21\xff\x0bNETSCAPE2.0\x03\x01\x00\x00\x00")?;
+        Ok(Encoder{out, width: w, height: h, colours: palette.len(), min_code_size})
+    }
+
+    /** Add a frame of width * height palette indexes, shown for delay 1/100 s */
+    pub fn frame(&mut self, pixels: &[u8], delay: u16) -> io::Result<()> {
+        let expected = usize::from(self.width) * usize::from(self.height);
+        if pixels.len() != expected {
+            return Err(invalid(format!("{} pixels instead of {}", pixels.len(), expected)));
+        }
+        if let Some(p) = pixels.iter().find(|p| usize::from(**p) >= self.colours) {
+            return Err(invalid(format!("colour {} not in the palette", p)));
+        }
+        // Graphic control extension, then the image descriptor
+        self.out.write_all(&[0x21, 0xf9, 0x04, 0x00])?;
+        self.out.write_all(&delay.to_le_bytes())?;
+        self.out.write_all(&[0x00, 0x00, 0x2c, 0, 0, 0, 0])?;
+        self.out.write_all(&self.width.to_le_bytes())?;
+        self.out.write_all(&self.height.to_le_bytes())?;
+        self.out.write_all(&[0x00, self.min_code_size])?;
+        for block in lzw_encode(pixels, self.min_code_size).chunks(255) {
+            self.out.write_all(&[block.len() as u8])?;
+            self.out.write_all(block)?;
+        }
+        self.out.write_all(&[0x00])
+    }
+
+    /** Write the trailer, give the output back */
+    pub fn finish(mut self) -> io::Result<W> {
+        self.out.write_all(&[0x3b])?;
+        self.out.flush()?;
+        Ok(self.out)
+    }
+}
+
+// ---------------------------------------------------------------------------
+// ---------------------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /** Reference decoder, following the GIF specification */
+    fn lzw_decode(data: &[u8], min_code_size: u8) -> Vec<u8> {
+        let clear = 1usize << min_code_size;
+        let mut output = Vec::new();
+        let mut table: Vec<Vec<u8>> = Vec::new();
+        let reset = |table: &mut Vec<Vec<u8>>| {
+            *table = (0..clear + 2).map(|i| vec![i as u8]).collect();
+        };
+        reset(&mut table);
+        let mut size = min_code_size + 1;
+        let mut previous: Option<Vec<u8>> = None;
+        let (mut buffer, mut bits, mut bytes) = (0u32, 0u8, data.iter());
+        loop {
+            while bits < size {
+                buffer |= u32::from(*bytes.next().expect("end code")) << bits;
+                bits += 8;
+            }
+            let code = (buffer & ((1 << size) - 1)) as usize;
+            buffer >>= size;
+            bits -= size;
+            if code == clear {
+                reset(&mut table);
+                size = min_code_size + 1;
+                previous = None;
+                continue;
+            }
+            if code == clear + 1 {
+                return output;
+            }
+            let entry = match (table.get(code), &previous) {
+                (Some(entry), _) => entry.clone(),
+                (None, Some(p)) => { let mut e = p.clone(); e.push(p[0]); e },
+                (None, None) => panic!("unknown first code {}", code),
+            };
+            output.extend_from_slice(&entry);
+            if let Some(mut p) = previous {
+                if table.len() < 1 << MAX_CODE_SIZE {
+                    p.push(entry[0]);
+                    table.push(p);
+                }
+            }
+            if table.len() == 1 << size && size < MAX_CODE_SIZE {
+                size += 1;
+            }
+            previous = Some(entry);
+        }
+    }
+
+    #[test]
+    fn check_lzw() {
+        // Sample image of the "What's in a GIF" article
+        let pixels: Vec<u8> = "\
+            1111122222 1111122222 1111122222 1110000222 1110000222 \
+            2220000111 2220000111 2222211111 2222211111 2222211111"
+            .bytes().filter(u8::is_ascii_digit).map(|b| b - b'0').collect();
+        assert_eq!(lzw_encode(&pixels, 2), vec![
+            0x8c, 0x2d, 0x99, 0x87, 0x2a, 0x1c, 0xdc, 0x33, 0xa0, 0x02, 0x75,
+            0xec, 0x95, 0xfa, 0xa8, 0xde, 0x60, 0x8c, 0x04, 0x91, 0x4c, 0x01]);
+        assert_eq!(lzw_decode(&lzw_encode(&pixels, 2), 2), pixels);
+        assert_eq!(lzw_decode(&lzw_encode(&[], 2), 2), vec![]);
+    }
+
+    #[test]
+    fn check_lzw_round_trip() {
+        // Enough data to fill the dictionary several times
+        let mut state = 7u32;
+        let mut noise = || { state = state.wrapping_mul(1_103_515_245).wrapping_add(12345); (state >> 16) as u8 };
+        let random: Vec<u8> = (0..50_000).map(|_| noise() % 4).collect();
+        assert_eq!(lzw_decode(&lzw_encode(&random, 2), 2), random);
+        let random: Vec<u8> = (0..50_000).map(|_| noise()).collect();
+        assert_eq!(lzw_decode(&lzw_encode(&random, 8), 8), random);
+        let flat = vec![3u8; 100_000];
+        assert_eq!(lzw_decode(&lzw_encode(&flat, 3), 3), flat);
+    }
+
+    #[test]
+    fn check_encoder() {
+        let palette = [[0, 0, 0], [255, 255, 255], [255, 0, 0]];
+        let mut gif = Encoder::new(Vec::new(), 3, 2, &palette).unwrap();
+        gif.frame(&[0, 1, 2, 2, 1, 0], 50).unwrap();
+        gif.frame(&[1, 1, 1, 1, 1, 1], 50).unwrap();
+        let bytes = gif.finish().unwrap();
+        assert_eq!(&bytes[..13], b"GIF89a\x03\x00\x02\x00\xf1\x00\x00");
+        // Palette padded to 4 colours
+        assert_eq!(&bytes[13..25], &[0, 0, 0, 255, 255, 255, 255, 0, 0, 0, 0, 0]);
+        assert_eq!(&bytes[25..28], b"\x21\xff\x0b");
+        assert_eq!(bytes.iter().filter(|b| **b == 0x2c).count(), 2);
+        assert_eq!(bytes.last(), Some(&0x3b));
+
+        assert!(Encoder::new(Vec::new(), 3, 2, &[]).is_err());
+        assert!(Encoder::new(Vec::new(), 100_000, 2, &palette).is_err());
+        let mut gif = Encoder::new(Vec::new(), 3, 2, &palette).unwrap();
+        assert!(gif.frame(&[0, 1, 2], 50).is_err());
+        assert!(gif.frame(&[0, 1, 2, 3, 1, 0], 50).is_err());
+    }
+}
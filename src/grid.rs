@@ -0,0 +1,324 @@
+/**
+ * Rectangular 2D grid of cells, parsed from a text map
+ *
+ * Positions are (row, column), row 0 is the first line of the input.
+ * Each character of the input is one cell, the mapping is given by the Cell
+ * trait. Cells are stored row by row in a single vector.
+ */
+use std::fmt;
+use std::ops::{Index, IndexMut};
+use std::str::FromStr;
+use crate::error::{ParseError, ParseErrorKind};
+
+// ---------------------------------------------------------------------------
+// Data types
+// ---------------------------------------------------------------------------
+/** Content of a grid cell, read from a single character */
+pub trait Cell: Sized {
+    /** Day of the puzzle, for parse errors */
+    const DAY: u32;
+    fn from_char(c: char) -> Option<Self>;
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Grid<T> {
+    rows: usize,
+    cols: usize,
+    cells: Vec<T>,
+}
+
+/** Up, down, left, right, as (row, column) steps */
+pub const DIRECTIONS4: [(isize, isize); 4] = [(-1, 0), (1, 0), (0, -1), (0, 1)];
+
+/** The 4 directions and the diagonals, clockwise from up-left */
+pub const DIRECTIONS8: [(isize, isize); 8] = [
+    (-1, -1), (-1, 0), (-1, 1), (0, 1), (1, 1), (1, 0), (1, -1), (0, -1),
+];
+
+// ---------------------------------------------------------------------------
+// Implementations
+// ---------------------------------------------------------------------------
+impl<T> Grid<T> {
+    /** Grid from its cells, row by row. None if the count is not rows * cols */
+    pub fn from_cells(rows: usize, cols: usize, cells: Vec<T>) -> Option<Self> {
+        if rows.checked_mul(cols) != Some(cells.len()) {
+            return None;
+        }
+        Some(Grid{rows, cols, cells})
+    }
+
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    pub fn cols(&self) -> usize {
+        self.cols
+    }
+
+    /** (rows, columns) */
+    pub fn size(&self) -> (usize, usize) {
+        (self.rows, self.cols)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cells.is_empty()
+    }
+
+    /** Index of a position in the row by row list of cells */
+    pub fn flat_index(&self, row: usize, col: usize) -> Option<usize> {
+        if row < self.rows && col < self.cols { Some(row * self.cols + col) } else { None }
+    }
+
+    /** Bounds-checked access */
+    pub fn get(&self, row: usize, col: usize) -> Option<&T> {
+        self.flat_index(row, col).map(|i| &self.cells[i])
+    }
+
+    pub fn get_mut(&mut self, row: usize, col: usize) -> Option<&mut T> {
+        self.flat_index(row, col).map(move |i| &mut self.cells[i])
+    }
+
+    /** Access with the columns repeating forever to the right (and left) */
+    pub fn get_wrapping(&self, row: usize, col: usize) -> Option<&T> {
+        if self.cols == 0 {
+            return None;
+        }
+        self.get(row, col % self.cols)
+    }
+
+    /** Position one step away in a direction, if inside the grid */
+    pub fn offset(&self, (row, col): (usize, usize), (dr, dc): (isize, isize)) -> Option<(usize, usize)> {
+        let row = row.checked_add_signed(dr)?;
+        let col = col.checked_add_signed(dc)?;
+        self.flat_index(row, col).map(|_| (row, col))
+    }
+
+    /** Positions around a cell in the given directions, inside the grid */
+    pub fn neighbours<'a>(&'a self, pos: (usize, usize), directions: &'a [(isize, isize)])
+        -> impl Iterator<Item = (usize, usize)> + 'a
+    {
+        directions.iter().filter_map(move |dir| self.offset(pos, *dir))
+    }
+
+    /** Up, down, left and right neighbours */
+    pub fn neighbours4(&self, pos: (usize, usize)) -> impl Iterator<Item = (usize, usize)> + '_ {
+        self.neighbours(pos, &DIRECTIONS4)
+    }
+
+    /** Neighbours including diagonals */
+    pub fn neighbours8(&self, pos: (usize, usize)) -> impl Iterator<Item = (usize, usize)> + '_ {
+        self.neighbours(pos, &DIRECTIONS8)
+    }
+
+    /** Positions seen from a cell in one direction, up to the border.
+     * The starting cell is not included. */
+    pub fn ray(&self, pos: (usize, usize), dir: (isize, isize)) -> impl Iterator<Item = (usize, usize)> + '_ {
+        let mut current = pos;
+        std::iter::from_fn(move || {
+            current = self.offset(current, dir)?;
+            Some(current)
+        })
+    }
+
+    pub fn row(&self, row: usize) -> Option<&[T]> {
+        if row < self.rows { Some(&self.cells[row * self.cols..(row + 1) * self.cols]) } else { None }
+    }
+
+    pub fn iter_rows(&self) -> impl Iterator<Item = &[T]> {
+        // chunks() refuses a size of 0
+        self.cells.chunks(self.cols.max(1))
+    }
+
+    pub fn column(&self, col: usize) -> impl Iterator<Item = &T> {
+        let start = if col < self.cols { col } else { self.cells.len() };
+        self.cells.iter().skip(start).step_by(self.cols.max(1))
+    }
+
+    /** Every position, row by row */
+    pub fn positions(&self) -> impl Iterator<Item = (usize, usize)> {
+        let cols = self.cols;
+        (0..self.rows).flat_map(move |row| (0..cols).map(move |col| (row, col)))
+    }
+
+    /** Every cell, row by row: the cell at (row, col) is at row * cols + col */
+    pub fn cells(&self) -> &[T] {
+        &self.cells
+    }
+
+    /** Every cell, row by row */
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        self.cells.iter()
+    }
+
+    /** Every cell with its position, row by row */
+    pub fn enumerate(&self) -> impl Iterator<Item = ((usize, usize), &T)> {
+        self.positions().zip(self.cells.iter())
+    }
+
+    /** Grid of the same size, with each cell transformed */
+    pub fn map<U, F: FnMut(&T) -> U>(&self, f: F) -> Grid<U> {
+        Grid{rows: self.rows, cols: self.cols, cells: self.cells.iter().map(f).collect()}
+    }
+}
+
+impl<T> Index<(usize, usize)> for Grid<T> {
+    type Output = T;
+    fn index(&self, (row, col): (usize, usize)) -> &T {
+        self.get(row, col).expect("position out of the grid")
+    }
+}
+
+impl<T> IndexMut<(usize, usize)> for Grid<T> {
+    fn index_mut(&mut self, (row, col): (usize, usize)) -> &mut T {
+        self.get_mut(row, col).expect("position out of the grid")
+    }
+}
+
+/** One line per row, every line of the same length. Empty input: empty grid */
+impl<T: Cell> FromStr for Grid<T> {
+    type Err = ParseError;
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let mut cells = Vec::new();
+        let mut rows = 0;
+        let mut cols = 0;
+        for (index, line) in input.lines().enumerate() {
+            let error = |kind| ParseError::new(T::DAY, kind).at_line(index + 1);
+            if line.is_empty() {
+                return Err(error(ParseErrorKind::Empty));
+            }
+            let mut count = 0;
+            for (i, c) in line.chars().enumerate() {
+                let cell = T::from_char(c).ok_or_else(|| error(ParseErrorKind::InvalidChar(c)).at_column(i + 1))?;
+                cells.push(cell);
+                count += 1;
+            }
+            if index == 0 {
+                cols = count;
+            } else if count != cols {
+                return Err(error(ParseErrorKind::Unexpected{
+                    expected: "a row as long as the first one",
+                    found: format!("{} cells instead of {}", count, cols),
+                }).at_column(count.min(cols) + 1));
+            }
+            rows += 1;
+        }
+        Ok(Grid{rows, cols, cells})
+    }
+}
+
+/** One line per row, each cell with its own Display */
+impl<T: fmt::Display> fmt::Display for Grid<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for row in self.iter_rows().take(self.rows) {
+            for cell in row.iter() {
+                write!(f, "{}", cell)?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+// ---------------------------------------------------------------------------
+// ---------------------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    struct Bit(bool);
+
+    impl Cell for Bit {
+        const DAY: u32 = 0;
+        fn from_char(c: char) -> Option<Self> {
+            match c {
+                '#' => Some(Bit(true)),
+                '.' => Some(Bit(false)),
+                _ => None,
+            }
+        }
+    }
+
+    impl fmt::Display for Bit {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "{}", if self.0 { '#' } else { '.' })
+        }
+    }
+
+    fn grid(input: &str) -> Grid<Bit> {
+        input.parse().unwrap()
+    }
+
+    #[test]
+    fn check_parse() {
+        let g = grid("#..\n.#.");
+        assert_eq!(g.size(), (2, 3));
+        assert_eq!(g[(1, 1)], Bit(true));
+        assert_eq!(format!("{}", g), "#..\n.#.\n");
+
+        let g = grid("");
+        assert_eq!(g.size(), (0, 0));
+        assert!(g.is_empty());
+        assert_eq!(format!("{}", g), "");
+
+        let err = "#.\n.x".parse::<Grid<Bit>>().unwrap_err();
+        assert_eq!(format!("{}", err), "day0 line 2 column 2: invalid character `x`");
+        let err = "#.\n\n..".parse::<Grid<Bit>>().unwrap_err();
+        assert_eq!((err.kind, err.line), (ParseErrorKind::Empty, 2));
+        let err = "#..\n.#\n".parse::<Grid<Bit>>().unwrap_err();
+        assert_eq!(format!("{}", err),
+                   "day0 line 2 column 3: expected a row as long as the first one, found `2 cells instead of 3`");
+    }
+
+    #[test]
+    fn check_get() {
+        let mut g = grid("#..\n.#.");
+        assert_eq!(g.get(0, 0), Some(&Bit(true)));
+        assert_eq!(g.get(2, 0), None);
+        assert_eq!(g.get(0, 3), None);
+        assert_eq!(g.get_wrapping(0, 3), Some(&Bit(true)));
+        assert_eq!(g.get_wrapping(1, 301), Some(&Bit(true)));
+        assert_eq!(g.get_wrapping(2, 0), None);
+        *g.get_mut(0, 2).unwrap() = Bit(true);
+        g[(1, 1)] = Bit(false);
+        assert_eq!(format!("{}", g), "#.#\n...\n");
+        assert_eq!(grid("").get_wrapping(0, 0), None);
+        assert_eq!(Grid::from_cells(2, 2, vec![1, 2, 3]), None);
+        assert_eq!(Grid::from_cells(1, 3, vec![1, 2, 3]).map(|g| g[(0, 2)]), Some(3));
+    }
+
+    #[test]
+    fn check_neighbours() {
+        let g = grid("...\n...\n...");
+        assert_eq!(g.neighbours4((1, 1)).count(), 4);
+        assert_eq!(g.neighbours8((1, 1)).count(), 8);
+        assert_eq!(g.neighbours4((0, 0)).collect::<Vec<_>>(), vec![(1, 0), (0, 1)]);
+        assert_eq!(g.neighbours8((0, 0)).collect::<Vec<_>>(), vec![(0, 1), (1, 1), (1, 0)]);
+        assert_eq!(g.neighbours8((2, 2)).count(), 3);
+        assert_eq!(g.neighbours((0, 1), &[(1, 1), (1, -1)]).collect::<Vec<_>>(), vec![(1, 2), (1, 0)]);
+    }
+
+    #[test]
+    fn check_ray() {
+        let g = grid("....\n....\n....");
+        assert_eq!(g.ray((0, 0), (1, 1)).collect::<Vec<_>>(), vec![(1, 1), (2, 2)]);
+        assert_eq!(g.ray((1, 3), (0, -1)).collect::<Vec<_>>(), vec![(1, 2), (1, 1), (1, 0)]);
+        assert_eq!(g.ray((2, 3), (1, 0)).count(), 0);
+        assert_eq!(g.ray((0, 0), (-1, 1)).count(), 0);
+    }
+
+    #[test]
+    fn check_rows_columns() {
+        let g = grid("#..\n.#.");
+        assert_eq!(g.row(1), Some(&[Bit(false), Bit(true), Bit(false)][..]));
+        assert_eq!(g.row(2), None);
+        assert_eq!(g.iter_rows().count(), 2);
+        assert_eq!(g.column(0).copied().collect::<Vec<_>>(), vec![Bit(true), Bit(false)]);
+        assert_eq!(g.column(3).count(), 0);
+        assert_eq!(g.positions().last(), Some((1, 2)));
+        assert_eq!(g.enumerate().filter(|(_, b)| b.0).map(|(p, _)| p).collect::<Vec<_>>(),
+                   vec![(0, 0), (1, 1)]);
+        assert_eq!(g.map(|b| b.0 as u8).iter().sum::<u8>(), 2);
+        assert_eq!(grid("").iter_rows().count(), 0);
+    }
+}
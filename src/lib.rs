@@ -3,9 +3,14 @@ extern crate aoc_runner;
 #[macro_use]
 extern crate aoc_runner_derive;
 
-// XXX error_chain seems to be deprecated
-//#[macro_use]
-//extern crate error_chain;
+pub mod error;
+#[macro_use]
+pub mod trace;
+
+pub mod console;
+pub mod gif;
+pub mod grid;
+pub mod math;
 
 pub mod day1;
 pub mod day2;
@@ -19,5 +24,6 @@ pub mod day9;
 pub mod day10;
 pub mod day11;
 pub mod day12;
+pub mod day13;
 
 aoc_lib!{ year = 2020 }
@@ -0,0 +1,214 @@
+/**
+ * Number theory helpers: gcd, modular arithmetic, Chinese remainder theorem
+ *
+ * Values are u64, intermediate products are computed on 128 bits so that
+ * nothing overflows for any modulus up to u64::MAX.
+ */
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt;
+
+// ---------------------------------------------------------------------------
+// Data types
+// ---------------------------------------------------------------------------
+/** Why a system of congruences has no usable solution */
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CrtError {
+    /** Congruence with a modulus of 0 */
+    ZeroModulus { index: usize },
+    /** Congruence incompatible with the previous ones */
+    Inconsistent { index: usize },
+    /** The combined modulus does not fit in an u64 */
+    Overflow { index: usize },
+}
+
+// ---------------------------------------------------------------------------
+// Implementations
+// ---------------------------------------------------------------------------
+impl fmt::Display for CrtError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CrtError::ZeroModulus{index} => write!(f, "congruence {}: modulus is 0", index),
+            CrtError::Inconsistent{index} =>
+                write!(f, "congruence {}: inconsistent with the previous ones", index),
+            CrtError::Overflow{index} => write!(f, "congruence {}: modulus overflows", index),
+        }
+    }
+}
+
+impl Error for CrtError {}
+
+pub fn gcd(mut a: u64, mut b: u64) -> u64 {
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    a
+}
+
+/** Least common multiple, None if it does not fit in an u64 */
+pub fn lcm(a: u64, b: u64) -> Option<u64> {
+    if a == 0 || b == 0 {
+        return Some(0);
+    }
+    (a / gcd(a, b)).checked_mul(b)
+}
+
+/** Extended Euclid: (g, x, y) with a*x + b*y = g = gcd(a, b), g >= 0 */
+pub fn extended_gcd(a: i128, b: i128) -> (i128, i128, i128) {
+    let (mut old_r, mut r) = (a, b);
+    let (mut old_x, mut x) = (1, 0);
+    let (mut old_y, mut y) = (0, 1);
+    while r != 0 {
+        let q = old_r / r;
+        (old_r, r) = (r, old_r - q * r);
+        (old_x, x) = (x, old_x - q * x);
+        (old_y, y) = (y, old_y - q * y);
+    }
+    if old_r < 0 {
+        (-old_r, -old_x, -old_y)
+    } else {
+        (old_r, old_x, old_y)
+    }
+}
+
+/** a * b mod m, m > 0 */
+pub fn mod_mul(a: u64, b: u64, m: u64) -> u64 {
+    (u128::from(a) * u128::from(b) % u128::from(m)) as u64
+}
+
+/** base^exp mod m by squaring, m > 0 */
+pub fn mod_pow(base: u64, mut exp: u64, m: u64) -> u64 {
+    let mut result = 1 % m;
+    let mut base = base % m;
+    while exp > 0 {
+        if exp & 1 == 1 {
+            result = mod_mul(result, base, m);
+        }
+        base = mod_mul(base, base, m);
+        exp >>= 1;
+    }
+    result
+}
+
+/** x in [0, m) with a*x ≡ 1 (mod m), None if a and m are not coprime */
+pub fn mod_inverse(a: u64, m: u64) -> Option<u64> {
+    if m == 0 {
+        return None;
+    }
+    let (g, x, _) = extended_gcd(i128::from(a % m), i128::from(m));
+    if g != 1 {
+        return None;
+    }
+    Some(x.rem_euclid(i128::from(m)) as u64)
+}
+
+/**
+ * Solve t ≡ r (mod m) for every (r, m) of the system.
+ * Moduli need not be coprime. Return (t, lcm of the moduli) with t the
+ * smallest solution: all the solutions are t + k * lcm.
+ */
+pub fn crt(congruences: &[(u64, u64)]) -> Result<(u64, u64), CrtError> {
+    let (mut t, mut modulus): (u64, u64) = (0, 1);
+    for (index, (r, m)) in congruences.iter().copied().enumerate() {
+        if m == 0 {
+            return Err(CrtError::ZeroModulus{index});
+        }
+        // t + modulus * k ≡ r (mod m)  <=>  modulus * k ≡ r - t (mod m)
+        let (g, inverse, _) = extended_gcd(i128::from(modulus), i128::from(m));
+        let diff = i128::from(r % m) - i128::from(t % m);
+        if diff % g != 0 {
+            return Err(CrtError::Inconsistent{index});
+        }
+        let step = (i128::from(m) / g) as u64;
+        let k = mod_mul((diff / g).rem_euclid(i128::from(step)) as u64,
+                        inverse.rem_euclid(i128::from(step)) as u64, step);
+        let combined = modulus.checked_mul(step).ok_or(CrtError::Overflow{index})?;
+        // modulus * k < combined, so the sum fits in an u128
+        t = u64::try_from((u128::from(t) + u128::from(modulus) * u128::from(k)) % u128::from(combined))
+            .map_err(|_| CrtError::Overflow{index})?;
+        modulus = combined;
+    }
+    Ok((t, modulus))
+}
+
+// ---------------------------------------------------------------------------
+// ---------------------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    #[test]
+    fn check_gcd() {
+        assert_eq!(gcd(12, 18), 6);
+        assert_eq!(gcd(0, 5), 5);
+        assert_eq!(lcm(4, 6), Some(12));
+        assert_eq!(lcm(u64::MAX, 2), None);
+        assert_eq!(extended_gcd(240, 46), (2, -9, 47));
+        assert_eq!(extended_gcd(-4, 6).0, 2);
+    }
+
+    #[test]
+    fn check_modular() {
+        assert_eq!(mod_inverse(3, 11), Some(4));
+        assert_eq!(mod_inverse(4, 6), None);
+        assert_eq!(mod_inverse(5, 1), Some(0));
+        assert_eq!(mod_pow(4, 13, 497), 445);
+        assert_eq!(mod_pow(7, 0, 1), 0);
+        // Fermat, with a modulus close to u64::MAX
+        let p = 18_446_744_073_709_551_557;
+        assert_eq!(mod_pow(123_456_789, p - 1, p), 1);
+        assert_eq!(mod_mul(p - 1, p - 1, p), 1);
+    }
+
+    #[test]
+    fn check_crt() {
+        assert_eq!(crt(&[(2, 3), (3, 5), (2, 7)]), Ok((23, 105)));
+        assert_eq!(crt(&[(2, 4), (4, 6)]), Ok((10, 12)));
+        assert_eq!(crt(&[]), Ok((0, 1)));
+        assert_eq!(crt(&[(1, 4), (2, 6)]), Err(CrtError::Inconsistent{index: 1}));
+        assert_eq!(crt(&[(1, 4), (2, 0)]), Err(CrtError::ZeroModulus{index: 1}));
+        let p = 4_294_967_291;
+        let q = 4_294_967_279;
+        assert_eq!(crt(&[(0, p), (0, q), (0, 7)]), Err(CrtError::Overflow{index: 2}));
+        let (t, m) = crt(&[(p - 1, p), (q - 2, q)]).unwrap();
+        assert_eq!((t % p, t % q, m), (p - 1, q - 2, p * q));
+        assert_eq!(format!("{}", CrtError::Inconsistent{index: 3}),
+                   "congruence 3: inconsistent with the previous ones");
+    }
+
+    proptest! {
+        #[test]
+        fn prop_extended_gcd(a in -1_000_000i128..1_000_000, b in -1_000_000i128..1_000_000) {
+            let (g, x, y) = extended_gcd(a, b);
+            prop_assert_eq!(a * x + b * y, g);
+            prop_assert_eq!(g as u64, gcd(a.unsigned_abs() as u64, b.unsigned_abs() as u64));
+        }
+
+        #[test]
+        fn prop_mod_inverse(a in 0u64..1000, m in 1u64..1000) {
+            let brute = (0..m).find(|x| a * x % m == 1 % m);
+            prop_assert_eq!(mod_inverse(a, m), brute);
+        }
+
+        #[test]
+        fn prop_mod_pow(base in 0u64..1000, exp in 0u64..20, m in 1u64..1000) {
+            let brute = (0..exp).fold(1 % m, |acc, _| acc * base % m);
+            prop_assert_eq!(mod_pow(base, exp, m), brute);
+        }
+
+        #[test]
+        fn prop_crt(system in prop::collection::vec((0u64..30, 1u64..30), 0..4)) {
+            let modulus = system.iter().fold(1, |l, (_, m)| lcm(l, *m).unwrap());
+            let brute = (0..modulus).find(|t| system.iter().all(|(r, m)| t % m == r % m));
+            match crt(&system) {
+                Ok((t, m)) => prop_assert_eq!((Some(t), m), (brute, modulus)),
+                Err(e) => {
+                    prop_assert_eq!(brute, None);
+                    let inconsistent = matches!(e, CrtError::Inconsistent{..});
+                    prop_assert!(inconsistent, "{}", e);
+                },
+            }
+        }
+    }
+}
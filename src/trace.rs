@@ -0,0 +1,180 @@
+/**
+ * Debug traces for the solvers, silent by default
+ *
+ * Traces are turned on per module with the AOC_TRACE environment variable,
+ * a comma separated list of `module[=level]`:
+ *
+ * ```text
+ * AOC_TRACE=day8=trace,day9 cargo aoc -d 8
+ * ```
+ *
+ * A bare module name enables the debug level, `all` matches every module.
+ * Traces are written on stderr, prefixed with the module name.
+ */
+use std::fmt;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::{OnceLock, RwLock};
+
+// ---------------------------------------------------------------------------
+// Data types
+// ---------------------------------------------------------------------------
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Off,
+    Info,
+    Debug,
+    Trace,
+}
+
+/** Level of each traced module, the longest matching name wins */
+#[derive(Debug, Default, PartialEq)]
+struct Filters {
+    modules: Vec<(String, Level)>,
+}
+
+/** Most verbose level enabled for any module, so that disabled traces cost
+ * a single atomic load */
+static MAX_LEVEL: AtomicU8 = AtomicU8::new(Level::Off as u8);
+static FILTERS: OnceLock<RwLock<Filters>> = OnceLock::new();
+
+// ---------------------------------------------------------------------------
+// Implementations
+// ---------------------------------------------------------------------------
+impl Level {
+    fn parse(input: &str) -> Option<Level> {
+        match input {
+            "off" => Some(Level::Off),
+            "info" => Some(Level::Info),
+            "debug" => Some(Level::Debug),
+            "trace" => Some(Level::Trace),
+            _ => None,
+        }
+    }
+}
+
+impl Filters {
+    /** Parse "day8=trace,day9", ignoring malformed entries */
+    fn parse(spec: &str) -> Filters {
+        let mut filters = Filters::default();
+        for entry in spec.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+            let mut parts = entry.splitn(2, '=');
+            let module = parts.next().unwrap_or(entry);
+            let level = match parts.next() {
+                None => Some(Level::Debug),
+                Some(level) => Level::parse(level),
+            };
+            if let Some(level) = level {
+                filters.set(module, level);
+            }
+        }
+        filters
+    }
+
+    fn set(&mut self, module: &str, level: Level) {
+        self.modules.retain(|(m, _)| m != module);
+        self.modules.push((String::from(module), level));
+    }
+
+    fn max_level(&self) -> Level {
+        self.modules.iter().map(|(_, l)| *l).max().unwrap_or(Level::Off)
+    }
+
+    /** Level for a module path such as "aoc_2020::day8" */
+    fn level_for(&self, path: &str) -> Level {
+        let module = short_name(path);
+        let mut best: Option<(usize, Level)> = None;
+        for (name, level) in self.modules.iter() {
+            let matching = name == "all" || module == name ||
+                module.strip_prefix(name.as_str()).is_some_and(|rest| rest.starts_with("::"));
+            let len = if name == "all" { 0 } else { name.len() };
+            if matching && best.is_none_or(|(l, _)| len >= l) {
+                best = Some((len, *level));
+            }
+        }
+        best.map_or(Level::Off, |(_, level)| level)
+    }
+}
+
+/** Module path without the crate name */
+fn short_name(path: &str) -> &str {
+    path.find("::").map_or(path, |i| &path[i + 2..])
+}
+
+fn filters() -> &'static RwLock<Filters> {
+    FILTERS.get_or_init(|| {
+        let filters = Filters::parse(&std::env::var("AOC_TRACE").unwrap_or_default());
+        MAX_LEVEL.store(filters.max_level() as u8, Ordering::Relaxed);
+        RwLock::new(filters)
+    })
+}
+
+/** True if traces of the given level are enabled for a module path */
+pub fn enabled(path: &str, level: Level) -> bool {
+    let filters = filters();
+    if level as u8 > MAX_LEVEL.load(Ordering::Relaxed) {
+        return false;
+    }
+    filters.read().is_ok_and(|f| f.level_for(path) >= level)
+}
+
+/** Change the level of a module at run time ("day8", or "all") */
+pub fn set_level(module: &str, level: Level) {
+    let mut filters = filters().write().unwrap_or_else(|e| e.into_inner());
+    filters.set(module, level);
+    MAX_LEVEL.store(filters.max_level() as u8, Ordering::Relaxed);
+}
+
+/** Write a trace line, used by the trace! macro */
+pub fn emit(path: &str, args: fmt::Arguments) {
+    eprintln!("[{}] {}", short_name(path), args);
+}
+
+/**
+ * Trace a message for the current module if its level is enabled:
+ *
+ * ```text
+ * trace!(Debug, "acc = {}", acc);
+ * ```
+ *
+ * Arguments are not evaluated when the trace is disabled.
+ */
+#[macro_export]
+macro_rules! trace {
+    ($level:ident, $($arg:tt)+) => {
+        if $crate::trace::enabled(module_path!(), $crate::trace::Level::$level) {
+            $crate::trace::emit(module_path!(), format_args!($($arg)+));
+        }
+    };
+}
+
+// ---------------------------------------------------------------------------
+// ---------------------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_parse() {
+        let filters = Filters::parse("day8=trace, day9,bogus=loud,,all=info");
+        assert_eq!(filters.modules, vec![
+            (String::from("day8"), Level::Trace),
+            (String::from("day9"), Level::Debug),
+            (String::from("all"), Level::Info),
+        ]);
+        assert_eq!(filters.max_level(), Level::Trace);
+        assert_eq!(Filters::parse("").max_level(), Level::Off);
+    }
+
+    #[test]
+    fn check_level_for() {
+        let filters = Filters::parse("all=info,day1=off,day8=trace");
+        assert_eq!(filters.level_for("aoc_2020::day8"), Level::Trace);
+        assert_eq!(filters.level_for("aoc_2020::day8::vm"), Level::Trace);
+        assert_eq!(filters.level_for("aoc_2020::day1"), Level::Off);
+        assert_eq!(filters.level_for("aoc_2020::day10"), Level::Info);
+
+        let filters = Filters::parse("day1");
+        assert_eq!(filters.level_for("aoc_2020::day1"), Level::Debug);
+        assert_eq!(filters.level_for("aoc_2020::day10"), Level::Off);
+    }
+}